@@ -0,0 +1,192 @@
+use super::background::Background;
+use crate::hittable::delta_light::DirectionalLight;
+use crate::hittable::environment::EnvironmentLight;
+use crate::material::phase::{HenyeyGreenstein, PhaseFunction, Rayleigh};
+use crate::utility::*;
+
+const VIEW_STEPS: usize = 32;
+const SUN_STEPS: usize = 8;
+
+// 球形行星上的大气, 空气分子(Rayleigh)和气溶胶(Mie)的密度都随高度指数衰减
+// 只计算太阳光的单次散射, 用作天空背景, 也用来给相机光线加上空气透视
+// 行星表面是场景中 y = 0 的平面, 球心在它的正下方; 内部长度单位都是米
+#[derive(Clone)]
+pub struct Atmosphere {
+    sun_dir: Vec3,       // 指向太阳
+    sun_irradiance: f64, // 大气层外太阳的辐照度
+    planet_radius: f64,
+    atmosphere_height: f64,
+    rayleigh_scattering: Color, // 海平面处的散射系数, Rayleigh散射没有吸收
+    rayleigh_height: f64,       // 标高, 密度为 exp(-h / H)
+    mie_scattering: f64,
+    mie_extinction: f64,
+    mie_height: f64,
+    mie_phase: HenyeyGreenstein,
+    meters_per_unit: f64, // 场景中一个单位对应的米数
+}
+
+impl Atmosphere {
+    // 默认取地球的参数(Bruneton和Hillaire的测量值)
+    pub fn new(sun_dir: Vec3, sun_irradiance: f64) -> Self {
+        Self {
+            sun_dir: sun_dir.unit_vector(),
+            sun_irradiance,
+            planet_radius: 6_360_000.0,
+            atmosphere_height: 100_000.0,
+            rayleigh_scattering: Color::new(5.802e-6, 13.558e-6, 33.1e-6),
+            rayleigh_height: 8000.0,
+            mie_scattering: 3.996e-6,
+            mie_extinction: 4.44e-6,
+            mie_height: 1200.0,
+            mie_phase: HenyeyGreenstein::new(0.8),
+            meters_per_unit: 1.0,
+        }
+    }
+
+    pub fn with_planet_radius(mut self, planet_radius: f64, atmosphere_height: f64) -> Self {
+        self.planet_radius = planet_radius;
+        self.atmosphere_height = atmosphere_height;
+        self
+    }
+
+    pub fn with_scale_heights(mut self, rayleigh_height: f64, mie_height: f64) -> Self {
+        self.rayleigh_height = rayleigh_height;
+        self.mie_height = mie_height;
+        self
+    }
+
+    pub fn with_meters_per_unit(mut self, meters_per_unit: f64) -> Self {
+        self.meters_per_unit = meters_per_unit;
+        self
+    }
+
+    // 场景坐标换算到以球心为原点的坐标
+    fn to_planet(&self, p: &Point3) -> Point3 {
+        *p * self.meters_per_unit + Vec3::new(0.0, self.planet_radius, 0.0)
+    }
+
+    // 单位向量dir的射线与以原点为球心的球的两个交点
+    fn intersect_sphere(orig: &Point3, dir: &Vec3, radius: f64) -> Option<(f64, f64)> {
+        let b = Vec3::dot(orig, dir);
+        let c = orig.length_squared() - radius * radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        Some((-b - sqrtd, -b + sqrtd))
+    }
+
+    // p处的消光系数, 以及Rayleigh和Mie的相对密度
+    fn extinction(&self, p: &Point3) -> (Color, f64, f64) {
+        let h = p.length() - self.planet_radius;
+        let rayleigh = (-h / self.rayleigh_height).exp();
+        let mie = (-h / self.mie_height).exp();
+        (
+            self.rayleigh_scattering * rayleigh
+                + Color::new(1.0, 1.0, 1.0) * self.mie_extinction * mie,
+            rayleigh,
+            mie,
+        )
+    }
+
+    // 从p到大气层外的太阳方向上的光学厚度, 被行星挡住时为None
+    fn sun_depth(&self, p: &Point3) -> Option<Color> {
+        if let Some((t, _)) = Atmosphere::intersect_sphere(p, &self.sun_dir, self.planet_radius) {
+            if t > 0.0 {
+                return None;
+            }
+        }
+        let top = self.planet_radius + self.atmosphere_height;
+        let t_max = Atmosphere::intersect_sphere(p, &self.sun_dir, top).map_or(0.0, |(_, t)| t);
+        let ds = t_max.max(0.0) / SUN_STEPS as f64;
+        let mut depth = Color::new(0.0, 0.0, 0.0);
+        for i in 0..SUN_STEPS {
+            let q = *p + self.sun_dir * ((i as f64 + 0.5) * ds);
+            depth += self.extinction(&q).0 * ds;
+        }
+        Some(depth)
+    }
+
+    // 沿单位向量dir走dist米(直到离开大气或到达地面)的透射率和散射进视线的光
+    fn integrate(&self, orig: &Point3, dir: &Vec3, dist: f64) -> (Color, Color) {
+        let none = (Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        let top = self.planet_radius + self.atmosphere_height;
+        let (t_min, mut t_max) = match Atmosphere::intersect_sphere(orig, dir, top) {
+            Some((t0, t1)) => (t0.max(0.0), t1.min(dist)),
+            None => return none,
+        };
+        if let Some((t, _)) = Atmosphere::intersect_sphere(orig, dir, self.planet_radius) {
+            if t > 0.0 {
+                t_max = t_max.min(t);
+            }
+        }
+        if t_max <= t_min {
+            return none;
+        }
+
+        // 光从太阳射来, 散射后沿-dir进入眼睛, 散射角余弦为 dot(sun_dir, dir)
+        let cos_theta = Vec3::dot(dir, &self.sun_dir);
+        let rayleigh_phase = self.rayleigh_scattering * Rayleigh.p(cos_theta);
+        let mie_phase = self.mie_scattering * self.mie_phase.p(cos_theta);
+
+        let ds = (t_max - t_min) / VIEW_STEPS as f64;
+        let mut depth = Color::new(0.0, 0.0, 0.0);
+        let mut inscatter = Color::new(0.0, 0.0, 0.0);
+        for i in 0..VIEW_STEPS {
+            let p = *orig + *dir * (t_min + (i as f64 + 0.5) * ds);
+            let (sigma_t, rayleigh, mie) = self.extinction(&p);
+            // 到采样点的光学厚度取到这一段的中点
+            let view_depth = depth + sigma_t * (0.5 * ds);
+            depth += sigma_t * ds;
+            if let Some(sun_depth) = self.sun_depth(&p) {
+                inscatter += exp_neg(view_depth + sun_depth)
+                    * (rayleigh_phase * rayleigh + Color::new(1.0, 1.0, 1.0) * mie_phase * mie)
+                    * ds;
+            }
+        }
+        (exp_neg(depth), inscatter * self.sun_irradiance)
+    }
+
+    // 相机到距离为dist(场景单位)处的表面之间的大气, 返回透射率和沿途散射进视线的光
+    pub fn aerial_perspective(&self, orig: &Point3, dir: &Vec3, dist: f64) -> (Color, Color) {
+        self.integrate(
+            &self.to_planet(orig),
+            &dir.unit_vector(),
+            dist * self.meters_per_unit,
+        )
+    }
+
+    // 与天空配套的太阳, 颜色为穿过大气后的衰减, 视直径约0.53度
+    pub fn sun_light(&self) -> DirectionalLight {
+        let tr = self
+            .sun_depth(&self.eye())
+            .map_or(Color::new(0.0, 0.0, 0.0), exp_neg);
+        DirectionalLight::new_with_angle(-self.sun_dir, tr * self.sun_irradiance, 0.53)
+    }
+
+    // 烘焙成环境贴图, 以便按亮度重要性采样
+    pub fn to_environment(&self, width: usize, height: usize) -> EnvironmentLight {
+        EnvironmentLight::new_from_background(self, width, height)
+    }
+
+    // 背景天空的观察点, 取地面上方1米处, 避免恰好在地面上
+    fn eye(&self) -> Point3 {
+        Point3::new(0.0, self.planet_radius + 1.0, 0.0)
+    }
+}
+
+fn exp_neg(depth: Color) -> Color {
+    Color::new((-depth.x).exp(), (-depth.y).exp(), (-depth.z).exp())
+}
+
+impl Background for Atmosphere {
+    fn value(&self, dir: &Vec3) -> Color {
+        self.integrate(&self.eye(), &dir.unit_vector(), f64::INFINITY)
+            .1
+    }
+
+    fn atmosphere(&self) -> Option<&Atmosphere> {
+        Some(self)
+    }
+}
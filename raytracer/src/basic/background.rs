@@ -0,0 +1,19 @@
+use super::atmosphere::Atmosphere;
+use crate::utility::*;
+
+// 光线没有击中任何物体时看到的颜色
+pub trait Background: Send + Sync {
+    fn value(&self, dir: &Vec3) -> Color;
+
+    // 有大气时相机光线还要加上空气透视
+    fn atmosphere(&self) -> Option<&Atmosphere> {
+        None
+    }
+}
+
+// 纯色背景
+impl Background for Color {
+    fn value(&self, dir: &Vec3) -> Color {
+        *self
+    }
+}
@@ -0,0 +1,45 @@
+use crate::utility::*;
+
+// 普朗克黑体辐射, lambda单位为nm, 只关心相对大小
+fn planck(lambda: f64, kelvin: f64) -> f64 {
+    let c = 299792458.0;
+    let h = 6.62606957e-34;
+    let kb = 1.3806488e-23;
+    let l = lambda * 1e-9;
+    2.0 * h * c * c / (l.powi(5) * ((h * c / (l * kb * kelvin)).exp() - 1.0))
+}
+
+// CIE 1931颜色匹配函数的多瓣高斯拟合(Wyman et al. 2013)
+fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// 色温为kelvin的黑体的颜色, 亮度归一化为1
+pub fn blackbody(kelvin: f64) -> Color {
+    if kelvin <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let mut xyz = Vec3::default();
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        xyz += cie_xyz(lambda) * planck(lambda, kelvin);
+        lambda += 5.0;
+    }
+    xyz /= xyz.y;
+
+    let rgb = Color::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+    Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
@@ -0,0 +1,249 @@
+use super::onb::ONB;
+use crate::utility::*;
+
+use console::style;
+use std::fs;
+
+// IES LM-63 光度文件描述的灯具配光曲线(C-γ坐标)
+// 垂直角0度为灯具的主轴方向, 水平角绕主轴旋转, 取值已按最大光强归一化
+#[derive(Clone)]
+pub struct IesProfile {
+    vertical: Vec<f64>,   // 垂直角(度), 递增
+    horizontal: Vec<f64>, // 水平角(度), 递增
+    candela: Vec<f64>,    // candela[h * vertical.len() + v]
+}
+
+impl IesProfile {
+    pub fn new_from_file(filename: &str) -> Self {
+        let text = match fs::read_to_string(filename) {
+            Ok(text) => text,
+            Err(_) => panic!("Couldn't open file: {}", filename),
+        };
+        match IesProfile::parse(&text) {
+            Some(profile) => profile,
+            None => panic!("Couldn't parse IES file: {}", filename),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        // 关键字行一直到TILT=为止
+        let mut lines = text.lines();
+        let tilt = loop {
+            let line = lines.next()?.trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<f64>());
+        let mut next = move || numbers.next().and_then(|n| n.ok());
+
+        if tilt == "INCLUDE" {
+            // 灯具的倾斜数据, 这里不使用
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            println!(
+                "  Warning: {}",
+                style("External TILT files are ignored in IES profiles").yellow()
+            );
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let vertical_num = next()? as usize;
+        let horizontal_num = next()? as usize;
+        let photometric_type = next()? as usize;
+        for _ in 0..7 {
+            // 单位, 尺寸, 镇流器系数等
+            next()?;
+        }
+        if photometric_type != 1 {
+            println!(
+                "  Warning: {}",
+                style("Only type C IES profiles are supported").yellow()
+            );
+        }
+
+        let mut read_n = |n: usize| (0..n).map(|_| next()).collect::<Option<Vec<f64>>>();
+        let vertical = read_n(vertical_num)?;
+        let horizontal = read_n(horizontal_num)?;
+        let mut candela = read_n(vertical_num * horizontal_num)?;
+
+        let max = candela.iter().cloned().fold(0.0, f64::max) * multiplier;
+        if max > 0.0 {
+            for c in candela.iter_mut() {
+                *c *= multiplier / max;
+            }
+        }
+
+        Some(Self {
+            vertical,
+            horizontal,
+            candela,
+        })
+    }
+
+    // 在有序数组中找到x所在的区间和插值系数, x不在数组范围内时返回None
+    fn locate(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+        let last = angles.len() - 1;
+        if x < angles[0] || x > angles[last] {
+            return None;
+        }
+        if x == angles[last] {
+            return Some((last, last, 0.0));
+        }
+        let i = angles.partition_point(|&a| a <= x) - 1;
+        let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+        Some((i, i + 1, t))
+    }
+
+    // 按对称性把水平角映射到文件给出的范围内
+    fn fold_horizontal(&self, phi: f64) -> f64 {
+        let last = *self.horizontal.last().unwrap();
+        if last <= 0.0 {
+            // 旋转对称
+            0.0
+        } else if last <= 90.0 {
+            // 四个象限对称
+            let phi = phi % 180.0;
+            if phi > 90.0 {
+                180.0 - phi
+            } else {
+                phi
+            }
+        } else if last <= 180.0 {
+            // 关于0-180度平面对称
+            if phi > 180.0 {
+                360.0 - phi
+            } else {
+                phi
+            }
+        } else {
+            phi
+        }
+    }
+
+    // 垂直角theta和水平角phi(度)方向上的相对光强, 文件没有给出的垂直角方向不发光
+    pub fn value(&self, theta: f64, phi: f64) -> f64 {
+        let (v0, v1, tv) = match IesProfile::locate(&self.vertical, theta) {
+            Some(v) => v,
+            None => return 0.0,
+        };
+        // 折叠后仍不在范围内的水平角(文件不完整)取第一个平面
+        let (h0, h1, th) =
+            IesProfile::locate(&self.horizontal, self.fold_horizontal(phi)).unwrap_or_default();
+        let n = self.vertical.len();
+        let c = |h: usize, v: usize| self.candela[h * n + v];
+        let c0 = c(h0, v0) * (1.0 - tv) + c(h0, v1) * tv;
+        let c1 = c(h1, v0) * (1.0 - tv) + c(h1, v1) * tv;
+        c0 * (1.0 - th) + c1 * th
+    }
+
+    // 单位向量dir相对于灯具坐标系的光强, axis为主轴, up在垂直于主轴的平面上的投影为水平角0度
+    pub fn value_in_dir(&self, axis: &Vec3, up: &Vec3, dir: &Vec3) -> f64 {
+        let uvw = IesProfile::frame(axis, up);
+        let dir = dir.unit_vector();
+        let theta = Vec3::dot(&dir, &uvw.w).clamp(-1.0, 1.0).acos().to_degrees();
+        let mut phi = f64::atan2(Vec3::dot(&dir, &uvw.v), Vec3::dot(&dir, &uvw.u)).to_degrees();
+        if phi < 0.0 {
+            phi += 360.0;
+        }
+        self.value(theta, phi)
+    }
+
+    fn frame(axis: &Vec3, up: &Vec3) -> ONB {
+        let w = axis.unit_vector();
+        let u = *up - w * Vec3::dot(up, &w);
+        if u.length_squared() < 1e-12 {
+            // up与主轴平行时水平角的起点没有意义, 任取一个
+            return ONB::build_from_w(w);
+        }
+        let u = u.unit_vector();
+        ONB {
+            u,
+            v: Vec3::cross(&w, &u),
+            w,
+        }
+    }
+
+    // 主轴一侧的半球上 weight(相对光强, 与主轴夹角的余弦) 对立体角的积分
+    // weight直接返回光强时, 均匀的配光曲线积分为2π
+    pub fn hemisphere_integral(&self, weight: impl Fn(f64, f64) -> f64) -> f64 {
+        let (theta_steps, phi_steps) = (90, 180);
+        let d_theta = 0.5 * PI / theta_steps as f64;
+        let d_phi = 2.0 * PI / phi_steps as f64;
+        let mut sum = 0.0;
+        for i in 0..theta_steps {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..phi_steps {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let value = self.value(theta.to_degrees(), phi.to_degrees());
+                sum += weight(value, theta.cos()) * theta.sin();
+            }
+        }
+        sum * d_theta * d_phi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 倾斜数据之后是 灯数, 流明, 乘数, 垂直角数, 水平角数, 类型, 单位, 三个尺寸 / 镇流器系数, 保留, 功率
+    fn profile(tilt: &str, horizontal: &str, candela: &str) -> IesProfile {
+        let horizontal_num = horizontal.split_whitespace().count();
+        let text = format!(
+            "IESNA:LM-63-2002\n[TEST] test\nTILT={}\n1 1000 2 3 {} 1 1 0.1 0.1 0\n1.0 1.0 0\n0 45 90\n{}\n{}\n",
+            tilt, horizontal_num, horizontal, candela
+        );
+        IesProfile::parse(&text).unwrap()
+    }
+
+    fn assert_near(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn skips_included_tilt_data() {
+        let tilt = "INCLUDE\n1\n3\n0 45 90\n1.0 0.9 0.8";
+        let p = profile(tilt, "0 90", "100 50 0\n200 100 0");
+        // 按最大光强归一化
+        assert_near(p.value(0.0, 0.0), 0.5);
+        assert_near(p.value(0.0, 90.0), 1.0);
+        assert_near(p.value(45.0, 45.0), 0.375);
+    }
+
+    #[test]
+    fn folds_horizontal_symmetry() {
+        // 旋转对称
+        let p = profile("NONE", "0", "100 50 0");
+        assert_near(p.value(22.5, 0.0), 0.75);
+        assert_near(p.value(22.5, 250.0), 0.75);
+
+        // 四个象限对称
+        let p = profile("NONE", "0 90", "100 50 0\n200 100 0");
+        assert_near(p.value(0.0, 180.0), 0.5);
+        assert_near(p.value(0.0, 270.0), 1.0);
+        assert_near(p.value(0.0, 135.0), 0.75);
+
+        // 关于0-180度平面对称
+        let p = profile("NONE", "0 90 180", "100 50 0\n200 100 0\n40 20 0");
+        assert_near(p.value(0.0, 270.0), 1.0);
+        assert_near(p.value(0.0, 225.0), 0.6);
+    }
+
+    #[test]
+    fn dark_outside_vertical_range() {
+        let p = profile("NONE", "0", "100 50 10");
+        assert_near(p.value(90.0, 0.0), 0.1);
+        assert_eq!(p.value(90.5, 0.0), 0.0);
+        assert_eq!(p.value(180.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::bvh::aabb::AABB;
+use crate::utility::*;
+
+use std::ops::Mul;
+
+// 仿射变换矩阵, 作用于列向量, 最后一行恒为 (0, 0, 0, 1)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            m: [
+                [1., 0., 0., 0.],
+                [0., 1., 0., 0.],
+                [0., 0., 1., 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self {
+            m: [
+                [1., 0., 0., offset.x],
+                [0., 1., 0., offset.y],
+                [0., 0., 1., offset.z],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        Self {
+            m: [
+                [s.x, 0., 0., 0.],
+                [0., s.y, 0., 0.],
+                [0., 0., s.z, 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn uniform_scale(s: f64) -> Self {
+        Mat4::scale(Vec3::new(s, s, s))
+    }
+
+    // 绕任意轴旋转angle度(右手系), Rodrigues公式
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit_vector();
+        let radians = angle.to_radians();
+        let (sin_theta, cos_theta) = (radians.sin(), radians.cos());
+        let k = 1.0 - cos_theta;
+        Self {
+            m: [
+                [
+                    a.x * a.x * k + cos_theta,
+                    a.x * a.y * k - a.z * sin_theta,
+                    a.x * a.z * k + a.y * sin_theta,
+                    0.,
+                ],
+                [
+                    a.y * a.x * k + a.z * sin_theta,
+                    a.y * a.y * k + cos_theta,
+                    a.y * a.z * k - a.x * sin_theta,
+                    0.,
+                ],
+                [
+                    a.z * a.x * k - a.y * sin_theta,
+                    a.z * a.y * k + a.x * sin_theta,
+                    a.z * a.z * k + cos_theta,
+                    0.,
+                ],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn rotation_x(angle: f64) -> Self {
+        Mat4::rotation(Vec3::new(1., 0., 0.), angle)
+    }
+
+    pub fn rotation_y(angle: f64) -> Self {
+        Mat4::rotation(Vec3::new(0., 1., 0.), angle)
+    }
+
+    pub fn rotation_z(angle: f64) -> Self {
+        Mat4::rotation(Vec3::new(0., 0., 1.), angle)
+    }
+
+    // 错切: x' = x + xy * y + xz * z, 其余同理
+    pub fn shear(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        Self {
+            m: [
+                [1., xy, xz, 0.],
+                [yx, 1., yz, 0.],
+                [zx, zy, 1., 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    // 把物体放在look_from处, 并让它的局部+z轴指向look_at
+    pub fn look_at(look_from: Point3, look_at: Point3, vup: Vec3) -> Self {
+        let w = (look_at - look_from).unit_vector();
+        let u = Vec3::cross(&vup, &w).unit_vector();
+        let v = Vec3::cross(&w, &u);
+        Self {
+            m: [
+                [u.x, v.x, w.x, look_from.x],
+                [u.y, v.y, w.y, look_from.y],
+                [u.z, v.z, w.z, look_from.z],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                m[i][j] = self.m[j][i];
+            }
+        }
+        Self { m }
+    }
+
+    // 高斯-约旦消元求逆, 矩阵奇异时返回None
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            let mut pivot = col;
+            for row in col + 1..4 {
+                if a[row][col].abs() > a[pivot][col].abs() {
+                    pivot = row;
+                }
+            }
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let k = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= k;
+                inv[col][j] *= k;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Self { m: inv })
+    }
+
+    // 左上3x3部分的行列式, 即体积的缩放比例
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        Point3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    // 变换后包围盒的精确范围(Arvo的方法), 比变换8个顶点再取范围更快
+    pub fn transform_box(&self, bbox: &AABB) -> AABB {
+        let mut min = Point3::default();
+        let mut max = Point3::default();
+        for i in 0..3 {
+            min[i] = self.m[i][3];
+            max[i] = self.m[i][3];
+            for j in 0..3 {
+                let a = self.m[i][j] * bbox.minimum[j];
+                let b = self.m[i][j] * bbox.maximum[j];
+                min[i] += a.min(b);
+                max[i] += a.max(b);
+            }
+        }
+        AABB::new(min, max)
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    m[i][j] += self.m[i][k] * rhs.m[k][j];
+                }
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mat_eq(a: &Mat4, b: &Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn inverse_times_matrix_is_identity() {
+        let m = Mat4::translation(Vec3::new(1., -2., 3.))
+            * Mat4::rotation(Vec3::new(1., 2., 3.), 37.)
+            * Mat4::scale(Vec3::new(2., 0.5, 3.))
+            * Mat4::shear(0.3, 0., 0., 0.2, 0.1, 0.);
+        let inv = m.inverse().unwrap();
+        assert_mat_eq(&(inv * m), &Mat4::identity());
+        assert_mat_eq(&(m * inv), &Mat4::identity());
+        assert!(Mat4::scale(Vec3::new(1., 0., 1.)).inverse().is_none());
+    }
+
+    #[test]
+    fn product_applies_right_matrix_first() {
+        let p = Point3::new(1., 0., 0.);
+        // 先旋转再平移
+        let m = Mat4::translation(Vec3::new(0., 0., 5.)) * Mat4::rotation_y(90.);
+        assert_vec_eq(m.transform_point(p), Point3::new(0., 0., 4.));
+        // 先平移再旋转
+        let m = Mat4::rotation_y(90.) * Mat4::translation(Vec3::new(0., 0., 5.));
+        assert_vec_eq(m.transform_point(p), Point3::new(5., 0., -1.));
+        // 先缩放再旋转, 缩放沿局部x轴
+        let m = Mat4::rotation_z(90.) * Mat4::scale(Vec3::new(2., 1., 1.));
+        assert_vec_eq(m.transform_point(p), Point3::new(0., 2., 0.));
+        // 向量不受平移影响
+        let m = Mat4::translation(Vec3::new(3., 3., 3.)) * Mat4::rotation_x(90.);
+        assert_vec_eq(
+            m.transform_vector(Vec3::new(0., 1., 0.)),
+            Vec3::new(0., 0., 1.),
+        );
+    }
+
+    #[test]
+    fn look_at_points_local_z_at_target() {
+        let look_from = Point3::new(1., 2., 3.);
+        let look_at = Point3::new(4., 2., -1.);
+        let m = Mat4::look_at(look_from, look_at, Vec3::new(0., 1., 0.));
+        assert_vec_eq(m.transform_point(Point3::default()), look_from);
+        assert_vec_eq(
+            m.transform_vector(Vec3::new(0., 0., 1.)),
+            (look_at - look_from).unit_vector(),
+        );
+        // 局部y轴仍在竖直平面内朝上
+        let up = m.transform_vector(Vec3::new(0., 1., 0.));
+        assert!(up.y > 0.0);
+        assert!(Vec3::dot(&up, &(look_at - look_from)).abs() < 1e-9);
+        assert!((m.determinant3() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transform_box_contains_transformed_corners() {
+        let bbox = AABB::new(Point3::new(-1., 0., 2.), Point3::new(3., 1., 5.));
+        let m =
+            Mat4::translation(Vec3::new(1., 2., 3.)) * Mat4::rotation(Vec3::new(1., 1., 0.), 30.);
+        let out = m.transform_box(&bbox);
+        for k in 0..8 {
+            let corner = Point3::new(
+                if k & 1 == 0 {
+                    bbox.minimum.x
+                } else {
+                    bbox.maximum.x
+                },
+                if k & 2 == 0 {
+                    bbox.minimum.y
+                } else {
+                    bbox.maximum.y
+                },
+                if k & 4 == 0 {
+                    bbox.minimum.z
+                } else {
+                    bbox.maximum.z
+                },
+            );
+            let p = m.transform_point(corner);
+            for i in 0..3 {
+                assert!(p[i] >= out.minimum[i] - 1e-9 && p[i] <= out.maximum[i] + 1e-9);
+            }
+        }
+    }
+}
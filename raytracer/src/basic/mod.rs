@@ -1,4 +1,10 @@
+pub mod atmosphere;
+pub mod background;
+pub mod blackbody;
 pub mod camera;
+pub mod ies;
+pub mod mat4;
 pub mod onb;
 pub mod ray;
+pub mod sky;
 pub mod vec3;
@@ -23,4 +23,13 @@ impl ONB {
     pub fn local(&self, a: Vec3) -> Vec3 {
         a.x * self.u + a.y * self.v + a.z * self.w
     }
+
+    // local的逆变换, 把世界坐标中的向量变换到该坐标系中
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(&a, &self.u),
+            Vec3::dot(&a, &self.v),
+            Vec3::dot(&a, &self.w),
+        )
+    }
 }
@@ -1,15 +1,10 @@
-use rand::Rng;
-
+use super::background::Background;
 pub use super::vec3::{Color, Point3, Vec3};
 use crate::{
-    hittable::{hittable_list::HittableList, Hittable},
-    material::DiffOrSpec,
-    pdf::{
-        cosine_pdf::CosinePDF,
-        hittable_pdf::HittablePDF,
-        mixture_pdf::{self, MixturePDF},
-        PDF,
-    },
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
+    material::{dielectric::InteriorStack, LobeFlags, ShadingContext},
+    medium::Medium,
+    pdf::{hittable_pdf::HittablePDF, PDF},
 };
 
 #[derive(Default, Clone, Copy, PartialEq, Debug)]
@@ -29,49 +24,409 @@ impl Ray {
     }
 }
 
+// 相机所在的介质取场景的全局介质, 相机不能放在附着了介质的物体内部
+// 背景带有大气时, 相机到第一个着色点之间按大气衰减并加上散射光
 pub fn ray_color(
     r: &Ray,
-    background: &Color,
+    background: &dyn Background,
     world: &HittableList,
     lights: &HittableList,
     depth: usize,
 ) -> Color {
+    let (color, dist) = trace(
+        r,
+        background,
+        world,
+        lights,
+        depth,
+        world.medium.as_deref(),
+        &InteriorStack::default(),
+        None,
+        Color::new(1.0, 1.0, 1.0),
+    );
+
+    // 空气透视只加在相机光线上, 没有击中物体时背景的天空已经包含了大气
+    match (background.atmosphere(), dist) {
+        (Some(atmosphere), Some(dist)) => {
+            let (tr, inscatter) = atmosphere.aerial_perspective(&r.orig, &r.dir, dist);
+            tr * color + inscatter
+        }
+        _ => color,
+    }
+}
+
+// 每个非镜面交点(包括介质中的散射点)都做一次光源采样, 路径本身只按材质采样延续, 两种策略按平衡启发式加权
+// medium为光线当前所在的介质, interiors为光线所在的电介质
+// prev为上一个散射点和按材质采样得到r的概率密度, 相机光线和镜面方向为None, 此时打到的光源不加权
+// beta为从相机到这里的路径吞吐量, 只用于在介质中选择采样的通道
+// 同时返回沿r到第一个着色点的距离, 穿过的介质边界和被覆盖的电介质表面不算, 打到背景时为None
+fn trace<'a>(
+    r: &Ray,
+    background: &dyn Background,
+    world: &'a HittableList,
+    lights: &HittableList,
+    depth: usize,
+    medium: Option<&'a dyn Medium>,
+    interiors: &InteriorStack,
+    prev: Option<(Point3, f64)>,
+    beta: Color,
+) -> (Color, Option<f64>) {
     if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
+        return (Color::new(0.0, 0.0, 0.0), None);
+    }
+
+    let hit = world.hit(r, 0.001, f64::INFINITY);
+    let hit_dist = hit.as_ref().map(|rec| rec.t * r.dir.length());
+
+    // 先在介质中采样自由程, 在到达表面之前散射时改为在散射点着色
+    let mut weight = Color::new(1.0, 1.0, 1.0);
+    let mut emitted = Color::new(0.0, 0.0, 0.0);
+    if let Some(m) = medium {
+        let t_max = hit.as_ref().map_or(f64::INFINITY, |rec| rec.t);
+        let sample = m.sample(r, t_max, &beta);
+        if sample.weight.near_zero() {
+            return (sample.emitted, hit_dist);
+        }
+        weight = sample.weight;
+        emitted = sample.emitted;
+        if let Some(rec) = sample.scatter {
+            let dist = rec.t * r.dir.length();
+            let weight = weight * interiors.transmittance(dist);
+            let color = shade(
+                r,
+                &rec,
+                background,
+                world,
+                lights,
+                depth,
+                medium,
+                interiors,
+                prev,
+                beta * weight,
+            );
+            return (emitted + weight * color, Some(dist));
+        }
+    }
+
+    let rec = match hit {
+        Some(rec) => rec,
+        None => {
+            let value = background.value(&r.dir);
+            return if value.near_zero() {
+                (emitted, None)
+            } else {
+                (emitted + weight * value * mis_weight(r, lights, prev), None)
+            };
+        }
+    };
+
+    // 从上一个交点到这里的路程被所在的电介质吸收
+    let dist = rec.t * r.dir.length();
+    weight = weight * interiors.transmittance(dist);
+
+    // 介质的边界不算一次反弹
+    if rec.mat_ptr.is_null() {
+        let next_medium = next_medium(world, &rec, &r.dir, medium);
+        let scattered = Ray::new(rec.p, r.dir, r.tm);
+        let (color, next_dist) = trace(
+            &scattered,
+            background,
+            world,
+            lights,
+            depth,
+            next_medium,
+            interiors,
+            prev,
+            beta * weight,
+        );
+        return (emitted + weight * color, next_dist.map(|d| dist + d));
     }
 
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        // let mut attenuation = Vec3::default();
-        let emmited = rec.mat_ptr.emitted(&r, &rec, rec.u, rec.v, rec.p);
-        return if let Some(scat_rec) = rec.mat_ptr.scatter(&r, &rec) {
-            match scat_rec.ray_type {
-                DiffOrSpec::Specular(scattered) => {
-                    scat_rec.attenuation
-                        * ray_color(&scattered, background, world, lights, depth - 1)
-                }
-                DiffOrSpec::Diffuse(cos_pdf) => {
-                    let light_pdf = HittablePDF::new(lights, rec.p);
-                    let mix_pdf = MixturePDF::new(light_pdf, cos_pdf);
-
-                    let scattered = Ray::new(rec.p, mix_pdf.generate(), r.tm);
-                    let pdf = mix_pdf.value(&scattered.dir);
-
-                    emmited
-                        + scat_rec.attenuation
-                            * rec.mat_ptr.scattering_pdf(r, &rec, &scattered)
-                            * ray_color(&scattered, background, world, lights, depth - 1)
-                            / pdf
-                }
+    // 被优先级更高的电介质覆盖的表面也直接穿过
+    if let Some(interior) = rec.mat_ptr.interior() {
+        if let (None, next_interiors) = interiors.cross(interior, rec.front_face) {
+            let scattered = Ray::new(rec.p, r.dir, r.tm);
+            let (color, next_dist) = trace(
+                &scattered,
+                background,
+                world,
+                lights,
+                depth,
+                medium,
+                &next_interiors,
+                prev,
+                beta * weight,
+            );
+            return (emitted + weight * color, next_dist.map(|d| dist + d));
+        }
+    }
+
+    let color = shade(
+        r,
+        &rec,
+        background,
+        world,
+        lights,
+        depth,
+        medium,
+        interiors,
+        prev,
+        beta * weight,
+    );
+    (emitted + weight * color, Some(dist))
+}
+
+// 在表面或介质中的散射点计算自发光, 直接光照, 再按材质采样继续追踪
+fn shade<'a>(
+    r: &Ray,
+    rec: &HitRecord,
+    background: &dyn Background,
+    world: &'a HittableList,
+    lights: &HittableList,
+    depth: usize,
+    medium: Option<&'a dyn Medium>,
+    interiors: &InteriorStack,
+    prev: Option<(Point3, f64)>,
+    beta: Color,
+) -> Color {
+    let mut emitted = rec.mat_ptr.emitted(r, rec, rec.u, rec.v, rec.p);
+    if !emitted.near_zero() {
+        emitted *= mis_weight(r, lights, prev);
+    }
+
+    // 电介质按两侧实际的折射率计算, 透射后进入或离开它的内部
+    let (ctx, crossed) = match rec
+        .mat_ptr
+        .interior()
+        .map(|interior| interiors.cross(interior, rec.front_face))
+    {
+        Some((Some(eta), next_interiors)) => {
+            (ShadingContext { eta: Some(eta) }, Some(next_interiors))
+        }
+        _ => (ShadingContext::default(), None),
+    };
+
+    // 纯镜面材质只能按材质采样
+    let direct = if rec.mat_ptr.flags().is_non_specular() {
+        let mut direct =
+            sample_delta_lights(r, rec, world, lights, medium, interiors, &crossed, &ctx);
+        if lights.has_area_lights() {
+            direct += sample_area_lights(
+                r, rec, background, world, lights, medium, interiors, &crossed, &ctx,
+            );
+        }
+        direct
+    } else {
+        Color::new(0.0, 0.0, 0.0)
+    };
+
+    // 材质采样的f和pdf取自采样结果, 分层材质等只能随机估计时也保持无偏
+    let sample = match rec.mat_ptr.sample(r, rec, &ctx) {
+        Some(sample) if sample.pdf > 0.0 && !sample.f.near_zero() => sample,
+        _ => return emitted + direct,
+    };
+    let next_interiors = match crossed {
+        Some(next_interiors) if sample.flags.contains(LobeFlags::TRANSMISSION) => next_interiors,
+        _ => interiors.clone(),
+    };
+    let next = if sample.flags.is_specular() {
+        None
+    } else {
+        Some((rec.p, sample.pdf))
+    };
+    let next_medium = next_medium(world, rec, &sample.wi, medium);
+    let scattered = Ray::new(sample.origin.unwrap_or(rec.p), sample.wi, r.tm);
+    let weight = sample.f / sample.pdf;
+    emitted
+        + direct
+        + weight
+            * trace(
+                &scattered,
+                background,
+                world,
+                lights,
+                depth - 1,
+                next_medium,
+                &next_interiors,
+                next,
+                beta * weight,
+            )
+            .0
+}
+
+// 材质采样打到光源的部分, 扣掉光源采样已经算过的比例
+fn mis_weight(r: &Ray, lights: &HittableList, prev: Option<(Point3, f64)>) -> f64 {
+    match prev {
+        Some((p, pdf)) if lights.has_area_lights() => {
+            let light_pdf = HittablePDF::new(lights, p, r.tm).value(&r.dir);
+            pdf / (pdf + light_pdf)
+        }
+        _ => 1.0,
+    }
+}
+
+// 沿dir离开交点后所在的介质
+// 穿过介质的边界时, 进入内部则为附着的介质, 离开则回到全局介质; 其他表面不改变介质
+fn next_medium<'a>(
+    world: &'a HittableList,
+    rec: &HitRecord<'a>,
+    dir: &Vec3,
+    current: Option<&'a dyn Medium>,
+) -> Option<&'a dyn Medium> {
+    match rec.medium {
+        Some(inside) => {
+            let outward_normal = if rec.front_face {
+                rec.normal
+            } else {
+                -rec.normal
+            };
+            if Vec3::dot(dir, &outward_normal) > 0.0 {
+                world.medium.as_deref()
+            } else {
+                Some(inside)
             }
-        } else {
-            emmited
+        }
+        None => current,
+    }
+}
+
+// 阴影光线所在的电介质, 指向透射一侧时已经穿过了交点所在的表面
+fn shadow_interiors<'a>(
+    rec: &HitRecord,
+    dir: &Vec3,
+    interiors: &'a InteriorStack,
+    crossed: &'a Option<InteriorStack>,
+) -> &'a InteriorStack {
+    match crossed {
+        Some(crossed) if Vec3::dot(dir, &rec.normal) < 0.0 => crossed,
+        _ => interiors,
+    }
+}
+
+// 阴影光线, 穿过介质的边界和被覆盖的电介质表面, 累计沿途介质和电介质的透射率
+// 返回透射率和dist以内第一个挡住光线的交点
+fn trace_shadow<'a>(
+    world: &'a HittableList,
+    orig: Point3,
+    dir: &Vec3,
+    dist: f64,
+    time: f64,
+    mut medium: Option<&'a dyn Medium>,
+    interiors: &InteriorStack,
+) -> (Color, Option<HitRecord<'a>>) {
+    let dir = dir.unit_vector();
+    let mut ray = Ray::new(orig, dir, time);
+    let mut tr = Color::new(1.0, 1.0, 1.0);
+    let mut dist = dist;
+    let mut interiors = interiors.clone();
+    loop {
+        let hit = world.hit(&ray, 0.001, dist);
+        // 方向是单位向量, t就是走过的距离
+        let t = hit.as_ref().map_or(dist, |rec| rec.t);
+        if let Some(medium) = medium {
+            tr = tr * medium.tr(&ray, t);
+        }
+        if t.is_finite() {
+            tr = tr * interiors.transmittance(t);
+        }
+        if tr.near_zero() {
+            return (Color::new(0.0, 0.0, 0.0), None);
+        }
+        let rec = match hit {
+            Some(rec) => rec,
+            None => return (tr, None),
         };
+        if rec.mat_ptr.is_null() {
+            medium = next_medium(world, &rec, &dir, medium);
+        } else {
+            match rec
+                .mat_ptr
+                .interior()
+                .map(|interior| interiors.cross(interior, rec.front_face))
+            {
+                Some((None, next_interiors)) => interiors = next_interiors,
+                _ => return (tr, Some(rec)),
+            }
+        }
+        dist -= rec.t;
+        ray = Ray::new(rec.p, dir, time);
+    }
+}
+
+// 按光源的概率密度采样一个方向, 与材质采样按平衡启发式加权
+// 阴影光线打到的第一个物体的自发光就是该方向上的入射光, 没打到物体时取背景
+fn sample_area_lights(
+    r: &Ray,
+    rec: &HitRecord,
+    background: &dyn Background,
+    world: &HittableList,
+    lights: &HittableList,
+    medium: Option<&dyn Medium>,
+    interiors: &InteriorStack,
+    crossed: &Option<InteriorStack>,
+    ctx: &ShadingContext,
+) -> Color {
+    let light_pdf = HittablePDF::new(lights, rec.p, r.tm);
+    let dir = light_pdf.generate();
+    let pdf = light_pdf.value(&dir);
+    if pdf <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let f = rec.mat_ptr.eval(r, rec, &dir, ctx);
+    if f.near_zero() {
+        return Color::new(0.0, 0.0, 0.0);
     }
 
-    *background
+    let medium = next_medium(world, rec, &dir, medium);
+    let interiors = shadow_interiors(rec, &dir, interiors, crossed);
+    let (tr, hit) = trace_shadow(world, rec.p, &dir, f64::INFINITY, r.tm, medium, interiors);
+    if tr.near_zero() {
+        return tr;
+    }
+    let incoming = match hit {
+        Some(hit) => {
+            let shadow_ray = Ray::new(rec.p, dir, r.tm);
+            hit.mat_ptr.emitted(&shadow_ray, &hit, hit.u, hit.v, hit.p)
+        }
+        None => background.value(&dir),
+    };
+    f * tr * incoming / (pdf + rec.mat_ptr.pdf(r, rec, &dir, ctx))
+}
 
-    // A sky background
-    // let unit_direction: Vec3 = r.dir.unit_vector();
-    // let t = 0.5 * (unit_direction.y + 1.0);
-    // (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+// 点光源等光线打不到, 对每个这样的光源做一次阴影测试
+// 返回值已经乘上材质的BSDF
+fn sample_delta_lights(
+    r: &Ray,
+    rec: &HitRecord,
+    world: &HittableList,
+    lights: &HittableList,
+    medium: Option<&dyn Medium>,
+    interiors: &InteriorStack,
+    crossed: &Option<InteriorStack>,
+    ctx: &ShadingContext,
+) -> Color {
+    let mut direct = Color::new(0.0, 0.0, 0.0);
+    for light in lights.objects.iter().filter(|light| light.is_delta()) {
+        if let Some(sample) = light.sample_li(&rec.p, r.tm) {
+            let response = rec.mat_ptr.eval(r, rec, &sample.dir, ctx);
+            if response.near_zero() {
+                continue;
+            }
+            let medium = next_medium(world, rec, &sample.dir, medium);
+            let interiors = shadow_interiors(rec, &sample.dir, interiors, crossed);
+            let (tr, hit) = trace_shadow(
+                world,
+                rec.p,
+                &sample.dir,
+                sample.dist,
+                r.tm,
+                medium,
+                interiors,
+            );
+            if hit.is_none() {
+                direct += sample.radiance * response * tr;
+            }
+        }
+    }
+    direct
 }
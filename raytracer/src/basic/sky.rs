@@ -0,0 +1,134 @@
+use super::background::Background;
+use crate::hittable::delta_light::DirectionalLight;
+use crate::hittable::environment::EnvironmentLight;
+use crate::utility::*;
+
+// Preetham等人的解析天空模型(A Practical Analytic Model for Daylight, 1999)
+// 由太阳方向和大气浑浊度(turbidity, 通常取2~10)决定天空各方向的颜色
+#[derive(Clone)]
+pub struct PreethamSky {
+    sun_dir: Vec3, // 指向太阳
+    turbidity: f64,
+    intensity: f64,
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yy: [f64; 5],      // 色度y的系数
+    zenith: (f64, f64, f64), // 天顶处的 (Y, x, y)
+}
+
+impl PreethamSky {
+    // intensity用来把模型的亮度(kcd/m^2)缩放到场景中合适的范围
+    pub fn new(sun_dir: Vec3, turbidity: f64, intensity: f64) -> Self {
+        let sun_dir = sun_dir.unit_vector();
+        let t = turbidity;
+        let theta_s = sun_dir.y.clamp(0.0, 1.0).acos();
+
+        let perez_y = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_yy = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s1, s2, s3) = (theta_s, theta_s.powi(2), theta_s.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s1)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s1 + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s1 + 0.25886);
+        let zenith_yy = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s1)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s1 + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s1 + 0.26688);
+
+        Self {
+            sun_dir,
+            turbidity,
+            intensity,
+            perez_y,
+            perez_x,
+            perez_yy,
+            zenith: (zenith_y, zenith_x, zenith_yy),
+        }
+    }
+
+    // Perez的天空亮度分布函数, theta为与天顶的夹角, gamma为与太阳的夹角
+    fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        (1.0 + c[0] * (c[1] / cos_theta).exp())
+            * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+    }
+
+    fn xyy_to_rgb(big_y: f64, x: f64, y: f64) -> Color {
+        let big_x = x / y * big_y;
+        let big_z = (1.0 - x - y) / y * big_y;
+        Color::new(
+            3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+        )
+    }
+
+    // 太阳光穿过大气后的颜色, 考虑Rayleigh散射和气溶胶(Angstrom公式)的衰减
+    fn sun_transmittance(&self) -> Color {
+        let theta_s = self.sun_dir.y.clamp(0.0, 1.0).acos();
+        // 相对光学质量
+        let m = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |lambda: f64| {
+            let tau_r = (-0.008735 * lambda.powf(-4.08) * m).exp();
+            let tau_a = (-beta * lambda.powf(-1.3) * m).exp();
+            tau_r * tau_a
+        };
+        // R, G, B分别取波长680nm, 550nm, 440nm(单位为微米)
+        Color::new(
+            transmittance(0.68),
+            transmittance(0.55),
+            transmittance(0.44),
+        )
+    }
+
+    // 与天空配套的太阳, 视直径约0.53度; irradiance为太阳在大气层外的辐照度
+    pub fn sun_light(&self, irradiance: f64) -> DirectionalLight {
+        DirectionalLight::new_with_angle(-self.sun_dir, self.sun_transmittance() * irradiance, 0.53)
+    }
+
+    // 烘焙成环境贴图, 以便按亮度重要性采样, 也可以导出来检查
+    pub fn to_environment(&self, width: usize, height: usize) -> EnvironmentLight {
+        EnvironmentLight::new_from_background(self, width, height)
+    }
+}
+
+impl Background for PreethamSky {
+    fn value(&self, dir: &Vec3) -> Color {
+        let dir = dir.unit_vector();
+        // 模型只定义在地平线以上, 地平线以下沿用地平线的颜色
+        let cos_theta = dir.y.max(0.01);
+        let gamma = Vec3::dot(&dir, &self.sun_dir).clamp(-1.0, 1.0).acos();
+        let theta_s = self.sun_dir.y.clamp(0.0, 1.0).acos();
+
+        let (zenith_y, zenith_x, zenith_yy) = self.zenith;
+        let big_y = zenith_y * PreethamSky::perez(&self.perez_y, cos_theta, gamma)
+            / PreethamSky::perez(&self.perez_y, 1.0, theta_s);
+        let x = zenith_x * PreethamSky::perez(&self.perez_x, cos_theta, gamma)
+            / PreethamSky::perez(&self.perez_x, 1.0, theta_s);
+        let y = zenith_yy * PreethamSky::perez(&self.perez_yy, cos_theta, gamma)
+            / PreethamSky::perez(&self.perez_yy, 1.0, theta_s);
+
+        let rgb = PreethamSky::xyy_to_rgb(big_y, x, y) * self.intensity;
+        Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+}
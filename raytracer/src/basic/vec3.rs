@@ -28,6 +28,10 @@ impl Color {
     pub fn to_u8_array(self) -> [u8; 3] {
         [self.x as u8, self.y as u8, self.z as u8]
     }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
 }
 
 impl Vec3 {
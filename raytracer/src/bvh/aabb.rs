@@ -29,6 +29,28 @@ impl AABB {
         true
     }
 
+    // 预先计算好方向倒数, 供BVH遍历时使用
+    pub fn hit_with_inv_dir(
+        &self,
+        orig: &Point3,
+        inv_dir: &Vec3,
+        mut t_min: f64,
+        mut t_max: f64,
+    ) -> bool {
+        for i in 0..3 {
+            let t0 = (self.minimum[i] - orig[i]) * inv_dir[i];
+            let t1 = (self.maximum[i] - orig[i]) * inv_dir[i];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        t_min <= t_max
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
         AABB::new(
             Point3::new(
@@ -0,0 +1,216 @@
+use super::aabb::AABB;
+use crate::hittable::{hittable_list::HittableList, HitRecord, Hittable};
+use crate::utility::*;
+
+use rand::Rng;
+use std::sync::Arc;
+
+const MAX_STACK_DEPTH: usize = 64;
+
+#[derive(Clone, Copy)]
+struct LightBvhNode {
+    node_box: AABB,
+    power: f64,    // 子树中光源的总功率(选择权重)
+    offset: usize, // 叶子: 光源的下标; 内部节点: 右孩子的下标
+    is_leaf: bool,
+}
+
+// 光源树: 每个叶子一个光源, 从根往下按各子树对着色点的估计贡献随机走到一个叶子
+pub struct LightBvh {
+    nodes: Vec<LightBvhNode>,
+    lights: Vec<Arc<dyn Hittable>>,
+}
+
+impl LightBvh {
+    // 有包围盒的面光源放进树里, 点光源和环境光等没有包围盒的光源留在树外
+    // 返回由光源树和这些光源组成的光源列表, 点光源仍能被逐个显式采样
+    pub fn build_light_list(list: &HittableList) -> HittableList {
+        let (bounded, others): (Vec<_>, Vec<_>) = list.objects.iter().cloned().partition(|light| {
+            light.can_sample_direction() && light.bounding_box(0.0, 1.0).is_some()
+        });
+
+        let mut lights = HittableList::default();
+        if !bounded.is_empty() {
+            lights.add(Arc::new(LightBvh::new(bounded)));
+        }
+        for light in others {
+            lights.add(light);
+        }
+        lights
+    }
+
+    fn new(lights: Vec<Arc<dyn Hittable>>) -> Self {
+        let mut list = HittableList::default();
+        for light in lights {
+            list.add(light);
+        }
+        let weights = list.light_weights();
+        let boxes: Vec<AABB> = list
+            .objects
+            .iter()
+            .map(|light| light.bounding_box(0.0, 1.0).unwrap())
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * list.objects.len());
+        let mut indices: Vec<usize> = (0..list.objects.len()).collect();
+        LightBvh::build(&boxes, &weights, &mut indices, &mut nodes);
+
+        // 叶子中存的是原列表的下标, 不需要重排光源
+        Self {
+            nodes,
+            lights: list.objects,
+        }
+    }
+
+    fn build(
+        boxes: &[AABB],
+        weights: &[f64],
+        indices: &mut [usize],
+        nodes: &mut Vec<LightBvhNode>,
+    ) -> usize {
+        let mut node_box = boxes[indices[0]];
+        let mut power = 0.0;
+        for &i in indices.iter() {
+            node_box = AABB::surrounding_box(&node_box, &boxes[i]);
+            power += weights[i];
+        }
+
+        let node_id = nodes.len();
+        nodes.push(LightBvhNode {
+            node_box,
+            power,
+            offset: indices[0],
+            is_leaf: true,
+        });
+        if indices.len() == 1 {
+            return node_id;
+        }
+
+        // 沿包围盒最长的轴按中位数划分
+        let extent = node_box.maximum - node_box.minimum;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        let centroid = |i: usize| (boxes[i].minimum[axis] + boxes[i].maximum[axis]) * 0.5;
+        let mid = indices.len() / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| {
+            f64::partial_cmp(&centroid(a), &centroid(b)).unwrap()
+        });
+
+        let (left, right) = indices.split_at_mut(mid);
+        LightBvh::build(boxes, weights, left, nodes);
+        let second_child = LightBvh::build(boxes, weights, right, nodes);
+
+        nodes[node_id].offset = second_child;
+        nodes[node_id].is_leaf = false;
+        node_id
+    }
+
+    // 子树对点p的估计贡献: 功率除以距离平方, p离得太近时用包围盒的半径代替距离
+    fn importance(node: &LightBvhNode, p: &Point3) -> f64 {
+        let center = (node.node_box.minimum + node.node_box.maximum) * 0.5;
+        let radius_squared = (node.node_box.maximum - node.node_box.minimum).length_squared() / 4.0;
+        node.power / (center - *p).length_squared().max(radius_squared)
+    }
+
+    // 在内部节点处选择左孩子的概率
+    fn left_prob(&self, node_id: usize, p: &Point3) -> f64 {
+        let left = LightBvh::importance(&self.nodes[node_id + 1], p);
+        let right = LightBvh::importance(&self.nodes[self.nodes[node_id].offset], p);
+        if left + right > 0.0 {
+            left / (left + right)
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Hittable for LightBvh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+
+        let mut stack = [0; MAX_STACK_DEPTH];
+        let mut stack_top = 1;
+        while stack_top > 0 {
+            stack_top -= 1;
+            let node_id = stack[stack_top];
+            let node = &self.nodes[node_id];
+            if !node.node_box.hit(r, t_min, closest_so_far) {
+                continue;
+            }
+            if node.is_leaf {
+                if let Some(rec) = self.lights[node.offset].hit(r, t_min, closest_so_far) {
+                    closest_so_far = rec.t;
+                    hit_record = Some(rec);
+                }
+            } else {
+                stack[stack_top] = node_id + 1;
+                stack[stack_top + 1] = node.offset;
+                stack_top += 2;
+            }
+        }
+
+        hit_record
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.nodes.first().map(|root| root.node_box)
+    }
+
+    // 只有包围盒被方向v穿过的光源才可能采到v, 沿途累乘选择概率
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+
+        let r = Ray::new(*orig, *v, time);
+        let mut pdf = 0.0;
+
+        let mut stack = [(0, 1.0); MAX_STACK_DEPTH];
+        let mut stack_top = 1;
+        while stack_top > 0 {
+            stack_top -= 1;
+            let (node_id, prob) = stack[stack_top];
+            let node = &self.nodes[node_id];
+            if prob == 0.0 || !node.node_box.hit(&r, 0.001, f64::INFINITY) {
+                continue;
+            }
+            if node.is_leaf {
+                pdf += prob * self.lights[node.offset].pdf_value(orig, v, time);
+            } else {
+                let left_prob = self.left_prob(node_id, orig);
+                stack[stack_top] = (node_id + 1, prob * left_prob);
+                stack[stack_top + 1] = (node.offset, prob * (1.0 - left_prob));
+                stack_top += 2;
+            }
+        }
+
+        pdf
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let mut node_id = 0;
+        while !self.nodes[node_id].is_leaf {
+            node_id = if rng.gen::<f64>() < self.left_prob(node_id, &orig) {
+                node_id + 1
+            } else {
+                self.nodes[node_id].offset
+            };
+        }
+        self.lights[self.nodes[node_id].offset].random(orig, time)
+    }
+
+    fn power(&self) -> f64 {
+        self.nodes.first().map_or(0.0, |root| root.power)
+    }
+}
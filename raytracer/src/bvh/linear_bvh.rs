@@ -0,0 +1,298 @@
+use super::aabb::AABB;
+use crate::hittable::{hittable_list::HittableList, HitRecord, Hittable};
+use crate::utility::multi_thread::THREAD_NUM;
+use crate::utility::*;
+
+use std::sync::Arc;
+use std::thread;
+
+const MAX_PRIMS_IN_NODE: usize = 4;
+const MAX_STACK_DEPTH: usize = 64;
+// 物体太少时并行不划算, 直接在当前线程建树
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
+#[derive(Clone, Copy)]
+pub(super) struct LinearBvhNode {
+    pub(super) node_box: AABB,
+    pub(super) offset: usize, // 叶子: 第一个物体的下标; 内部节点: 右孩子的下标
+    pub(super) prim_num: usize, // 内部节点为0
+    pub(super) axis: usize,
+}
+
+struct PrimInfo {
+    prim_box: AABB,
+    centroid: Point3,
+}
+
+// 节点按深度优先顺序存放在一个数组里, 左孩子紧跟在父节点之后
+pub struct LinearBvh {
+    pub(super) nodes: Vec<LinearBvhNode>,
+    pub(super) objects: Vec<Arc<dyn Hittable>>,
+    pub node_box: AABB,
+}
+
+impl LinearBvh {
+    pub fn new_from_list(list: &HittableList, st_time: f64, ed_time: f64) -> Self {
+        LinearBvh::new_from_vec(list.objects.clone(), st_time, ed_time)
+    }
+
+    pub fn new_from_vec(objects: Vec<Arc<dyn Hittable>>, st_time: f64, ed_time: f64) -> Self {
+        if objects.is_empty() {
+            return Self {
+                nodes: Vec::new(),
+                objects,
+                node_box: AABB::default(),
+            };
+        }
+
+        let objects = Arc::new(objects);
+        let prims = Arc::new(LinearBvh::compute_prim_info(&objects, st_time, ed_time));
+        // 退化三角形等的包围盒可能含NaN, 无法排序也不会被击中, 直接丢掉
+        let indices: Vec<usize> = (0..objects.len())
+            .filter(|&i| {
+                let c = prims[i].centroid;
+                c.x.is_finite() && c.y.is_finite() && c.z.is_finite()
+            })
+            .collect();
+        if indices.is_empty() {
+            return Self {
+                nodes: Vec::new(),
+                objects: Vec::new(),
+                node_box: AABB::default(),
+            };
+        }
+        let depth = (THREAD_NUM as f64).log2().ceil() as usize;
+        let (nodes, indices) = LinearBvh::build_parallel(prims, indices, 0, depth);
+
+        // 按叶子顺序重新排列物体, 使叶子能用区间引用
+        let objects = indices.iter().map(|&i| objects[i].clone()).collect();
+
+        Self {
+            node_box: nodes[0].node_box,
+            nodes,
+            objects,
+        }
+    }
+
+    fn prim_info(obj: &Arc<dyn Hittable>, st_time: f64, ed_time: f64) -> PrimInfo {
+        let prim_box = obj.bounding_box(st_time, ed_time).unwrap();
+        PrimInfo {
+            prim_box,
+            centroid: (prim_box.minimum + prim_box.maximum) * 0.5,
+        }
+    }
+
+    // 物体多时分成若干段, 每段开一个线程计算包围盒
+    fn compute_prim_info(
+        objects: &Arc<Vec<Arc<dyn Hittable>>>,
+        st_time: f64,
+        ed_time: f64,
+    ) -> Vec<PrimInfo> {
+        if objects.len() < PARALLEL_BUILD_THRESHOLD {
+            return objects
+                .iter()
+                .map(|obj| LinearBvh::prim_info(obj, st_time, ed_time))
+                .collect();
+        }
+
+        let chunk_size = objects.len() / THREAD_NUM + 1;
+        let handles: Vec<_> = (0..objects.len())
+            .step_by(chunk_size)
+            .map(|st| {
+                let objects = objects.clone();
+                let ed = (st + chunk_size).min(objects.len());
+                thread::spawn(move || {
+                    objects[st..ed]
+                        .iter()
+                        .map(|obj| LinearBvh::prim_info(obj, st_time, ed_time))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    }
+
+    // 上面depth层每次划分后把右子树交给新线程, 自己继续建左子树, 再把两棵子树拼到根节点后面
+    // 物体较少或深度用完时在当前线程建完整棵子树
+    // 返回按深度优先顺序排列的节点和重新排列后的物体下标, 节点下标从0开始
+    fn build_parallel(
+        prims: Arc<Vec<PrimInfo>>,
+        mut indices: Vec<usize>,
+        first: usize,
+        depth: usize,
+    ) -> (Vec<LinearBvhNode>, Vec<usize>) {
+        if depth == 0 || indices.len() < PARALLEL_BUILD_THRESHOLD {
+            let mut nodes = Vec::with_capacity(2 * indices.len());
+            LinearBvh::build(&prims, &mut indices, first, &mut nodes);
+            return (nodes, indices);
+        }
+
+        let (node_box, split) = LinearBvh::partition(&prims, &mut indices);
+        let leaf = LinearBvhNode {
+            node_box,
+            offset: first,
+            prim_num: indices.len(),
+            axis: 0,
+        };
+        let (axis, mid) = match split {
+            Some(split) => split,
+            None => return (vec![leaf], indices),
+        };
+
+        let right = indices.split_off(mid);
+        let right_prims = prims.clone();
+        let handle = thread::spawn(move || {
+            LinearBvh::build_parallel(right_prims, right, first + mid, depth - 1)
+        });
+        let (left_nodes, mut indices) = LinearBvh::build_parallel(prims, indices, first, depth - 1);
+        let (right_nodes, right) = handle.join().unwrap();
+        indices.extend(right);
+
+        let mut nodes = Vec::with_capacity(1 + left_nodes.len() + right_nodes.len());
+        nodes.push(LinearBvhNode {
+            offset: 1 + left_nodes.len(),
+            prim_num: 0,
+            axis,
+            ..leaf
+        });
+        for sub in [left_nodes, right_nodes].iter() {
+            let base = nodes.len();
+            nodes.extend(sub.iter().map(|&node| {
+                let mut node = node;
+                if node.prim_num == 0 {
+                    node.offset += base;
+                }
+                node
+            }));
+        }
+        (nodes, indices)
+    }
+
+    // 递归建树, 返回当前子树根节点的下标
+    fn build(
+        prims: &[PrimInfo],
+        indices: &mut [usize],
+        first: usize,
+        nodes: &mut Vec<LinearBvhNode>,
+    ) -> usize {
+        let (node_box, split) = LinearBvh::partition(prims, indices);
+
+        let node_id = nodes.len();
+        nodes.push(LinearBvhNode {
+            node_box,
+            offset: first,
+            prim_num: indices.len(),
+            axis: 0,
+        });
+        let (axis, mid) = match split {
+            Some(split) => split,
+            None => return node_id,
+        };
+
+        let (left, right) = indices.split_at_mut(mid);
+        LinearBvh::build(prims, left, first, nodes);
+        let second_child = LinearBvh::build(prims, right, first + mid, nodes);
+
+        nodes[node_id].offset = second_child;
+        nodes[node_id].prim_num = 0;
+        nodes[node_id].axis = axis;
+        node_id
+    }
+
+    // 计算节点的包围盒, 并沿质心跨度最大的轴按中位数划分
+    // 返回划分轴和中点, 应当作为叶子时返回None
+    fn partition(prims: &[PrimInfo], indices: &mut [usize]) -> (AABB, Option<(usize, usize)>) {
+        let mut node_box = prims[indices[0]].prim_box;
+        let mut centroid_box = AABB::new(prims[indices[0]].centroid, prims[indices[0]].centroid);
+        for &i in indices.iter() {
+            node_box = AABB::surrounding_box(&node_box, &prims[i].prim_box);
+            centroid_box = AABB::surrounding_box(
+                &centroid_box,
+                &AABB::new(prims[i].centroid, prims[i].centroid),
+            );
+        }
+
+        let extent = centroid_box.maximum - centroid_box.minimum;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        if indices.len() <= MAX_PRIMS_IN_NODE || extent[axis] <= 0.0 {
+            return (node_box, None);
+        }
+
+        let mid = indices.len() / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| {
+            f64::partial_cmp(&prims[a].centroid[axis], &prims[b].centroid[axis]).unwrap()
+        });
+        (node_box, Some((axis, mid)))
+    }
+}
+
+impl Hittable for LinearBvh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_dir = Vec3::new(1.0 / r.dir.x, 1.0 / r.dir.y, 1.0 / r.dir.z);
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+
+        let mut stack = [0; MAX_STACK_DEPTH];
+        let mut stack_top = 0;
+        let mut cur = 0;
+        loop {
+            let node = &self.nodes[cur];
+            if node
+                .node_box
+                .hit_with_inv_dir(&r.orig, &inv_dir, t_min, closest_so_far)
+            {
+                if node.prim_num > 0 {
+                    for obj in self.objects[node.offset..node.offset + node.prim_num].iter() {
+                        if let Some(rec) = obj.hit(r, t_min, closest_so_far) {
+                            closest_so_far = rec.t;
+                            hit_record = Some(rec);
+                        }
+                    }
+                } else {
+                    // 先访问离光线起点更近的孩子
+                    if dir_is_neg[node.axis] {
+                        stack[stack_top] = cur + 1;
+                        cur = node.offset;
+                    } else {
+                        stack[stack_top] = node.offset;
+                        cur += 1;
+                    }
+                    stack_top += 1;
+                    continue;
+                }
+            }
+
+            if stack_top == 0 {
+                break;
+            }
+            stack_top -= 1;
+            cur = stack[stack_top];
+        }
+
+        hit_record
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        if self.nodes.is_empty() {
+            None
+        } else {
+            Some(self.node_box)
+        }
+    }
+}
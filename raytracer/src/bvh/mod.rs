@@ -1,2 +1,6 @@
 pub mod aabb;
 pub mod bvh_node;
+pub mod light_bvh;
+pub mod linear_bvh;
+pub mod tlas;
+pub mod wide_bvh;
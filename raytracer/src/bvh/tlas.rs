@@ -0,0 +1,35 @@
+use super::aabb::AABB;
+use super::linear_bvh::LinearBvh;
+use crate::hittable::instance::mesh_instance::MeshInstance;
+use crate::hittable::{HitRecord, Hittable};
+use crate::utility::*;
+
+use std::sync::Arc;
+
+// 顶层加速结构(TLAS): 在网格实例之上再建一层BVH
+// 每个实例只保存变换和共享网格的引用, 网格本身的BVH只建一次
+pub struct Tlas {
+    instances: LinearBvh,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<MeshInstance>, st_time: f64, ed_time: f64) -> Self {
+        let objects = instances
+            .into_iter()
+            .map(|instance| Arc::new(instance) as Arc<dyn Hittable>)
+            .collect();
+        Self {
+            instances: LinearBvh::new_from_vec(objects, st_time, ed_time),
+        }
+    }
+}
+
+impl Hittable for Tlas {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.instances.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.instances.bounding_box(st_time, ed_time)
+    }
+}
@@ -0,0 +1,443 @@
+use super::aabb::AABB;
+use super::linear_bvh::LinearBvh;
+use crate::hittable::{hittable_list::HittableList, HitRecord, Hittable};
+use crate::utility::*;
+
+use std::sync::Arc;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+pub type WideBvh4 = WideBvh<4>;
+pub type WideBvh8 = WideBvh<8>;
+
+// 栈不超过这个大小时放在栈上, 否则按树的深度分配
+const MAX_STACK_SIZE: usize = 256;
+// f32包围盒向外扩一点, 保证不会漏掉f64下的交点
+const BOX_PAD: f64 = 1e-6;
+
+// 孩子的包围盒按分量分开存放(SoA), 一次测试所有孩子
+#[derive(Clone, Copy)]
+#[repr(C, align(32))]
+struct WideBvhNode<const N: usize> {
+    min_x: [f32; N],
+    min_y: [f32; N],
+    min_z: [f32; N],
+    max_x: [f32; N],
+    max_y: [f32; N],
+    max_z: [f32; N],
+    child: [u32; N],    // 叶子: 第一个物体的下标; 内部节点: 孩子节点的下标
+    prim_num: [u32; N], // 内部节点为0
+}
+
+impl<const N: usize> WideBvhNode<N> {
+    fn empty() -> Self {
+        // 空位的包围盒是反的, 不会被任何光线击中
+        Self {
+            min_x: [f32::INFINITY; N],
+            min_y: [f32::INFINITY; N],
+            min_z: [f32::INFINITY; N],
+            max_x: [f32::NEG_INFINITY; N],
+            max_y: [f32::NEG_INFINITY; N],
+            max_z: [f32::NEG_INFINITY; N],
+            child: [0; N],
+            prim_num: [0; N],
+        }
+    }
+
+    // scale是整棵树坐标的量级, 用来覆盖光线起点转成f32时的误差
+    fn set_box(&mut self, i: usize, bbox: &AABB, scale: f64) {
+        let lo = |x: f64| (x - (x.abs() + scale) * BOX_PAD) as f32;
+        let hi = |x: f64| (x + (x.abs() + scale) * BOX_PAD) as f32;
+        self.min_x[i] = lo(bbox.minimum.x);
+        self.min_y[i] = lo(bbox.minimum.y);
+        self.min_z[i] = lo(bbox.minimum.z);
+        self.max_x[i] = hi(bbox.maximum.x);
+        self.max_y[i] = hi(bbox.maximum.y);
+        self.max_z[i] = hi(bbox.maximum.z);
+    }
+}
+
+// 光线在f32下的表示, 根据方向的正负预先选好每个轴的近平面和远平面
+struct WideRay {
+    orig: [f32; 3],
+    inv_dir: [f32; 3],
+    dir_is_neg: [bool; 3],
+}
+
+impl WideRay {
+    fn new(r: &Ray) -> Self {
+        let mut orig = [0.0; 3];
+        let mut inv_dir = [0.0; 3];
+        let mut dir_is_neg = [false; 3];
+        for i in 0..3 {
+            orig[i] = r.orig[i] as f32;
+            // 避免 0 * inf 得到 NaN
+            inv_dir[i] = ((1.0 / r.dir[i]) as f32).clamp(-1e30, 1e30);
+            // 按inv_dir判断, 分量为-0.0时与倒数的符号一致
+            dir_is_neg[i] = inv_dir[i] < 0.0;
+        }
+        Self {
+            orig,
+            inv_dir,
+            dir_is_neg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct StackEntry {
+    child: u32,
+    prim_num: u32,
+    t_near: f32,
+}
+
+pub struct WideBvh<const N: usize> {
+    nodes: Vec<WideBvhNode<N>>,
+    objects: Vec<Arc<dyn Hittable>>,
+    use_avx: bool,
+    stack_size: usize,
+    pub node_box: AABB,
+}
+
+impl<const N: usize> WideBvh<N> {
+    pub fn new_from_list(list: &HittableList, st_time: f64, ed_time: f64) -> Self {
+        WideBvh::new_from_binary(LinearBvh::new_from_list(list, st_time, ed_time))
+    }
+
+    // 把二叉BVH的若干层合并成一个N叉节点
+    pub fn new_from_binary(bvh: LinearBvh) -> Self {
+        assert!(
+            (2..=8).contains(&N),
+            "WideBvh only supports 2 to 8 children."
+        );
+
+        let mut wide_bvh = Self {
+            nodes: Vec::new(),
+            objects: Vec::new(),
+            use_avx: WideBvh::<N>::detect_avx(),
+            stack_size: 0,
+            node_box: bvh.node_box,
+        };
+        if !bvh.nodes.is_empty() {
+            let scale = [bvh.node_box.minimum, bvh.node_box.maximum]
+                .iter()
+                .map(|p| p.x.abs().max(p.y.abs()).max(p.z.abs()))
+                .fold(0.0, f64::max);
+            wide_bvh.collapse(&bvh, 0, scale);
+            // 每下降一层最多留下N-1个兄弟在栈中
+            wide_bvh.stack_size = wide_bvh.depth(0) * (N - 1) + 1;
+        }
+        wide_bvh.objects = bvh.objects;
+        wide_bvh
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn detect_avx() -> bool {
+        N == 8 && is_x86_feature_detected!("avx")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn detect_avx() -> bool {
+        false
+    }
+
+    fn depth(&self, node_id: usize) -> usize {
+        let node = &self.nodes[node_id];
+        let mut depth = 0;
+        for i in 0..N {
+            // 空位的包围盒是反的, 不是内部节点
+            if node.prim_num[i] == 0 && node.min_x[i] <= node.max_x[i] {
+                depth = depth.max(self.depth(node.child[i] as usize));
+            }
+        }
+        depth + 1
+    }
+
+    fn collapse(&mut self, bvh: &LinearBvh, bin_node: usize, scale: f64) -> u32 {
+        let node_id = self.nodes.len();
+        self.nodes.push(WideBvhNode::empty());
+
+        let mut slots = if bvh.nodes[bin_node].prim_num > 0 {
+            vec![bin_node]
+        } else {
+            vec![bin_node + 1, bvh.nodes[bin_node].offset]
+        };
+
+        // 每次展开表面积最大的内部节点, 直到填满N个孩子
+        while slots.len() < N {
+            let mut best = None;
+            let mut best_area = f64::NEG_INFINITY;
+            for (k, &s) in slots.iter().enumerate() {
+                let area = bvh.nodes[s].node_box.surface_area();
+                if bvh.nodes[s].prim_num == 0 && area > best_area {
+                    best = Some(k);
+                    best_area = area;
+                }
+            }
+            match best {
+                Some(k) => {
+                    let s = slots[k];
+                    slots[k] = s + 1;
+                    slots.push(bvh.nodes[s].offset);
+                }
+                None => break,
+            }
+        }
+
+        for (k, &s) in slots.iter().enumerate() {
+            let bin = bvh.nodes[s];
+            let (child, prim_num) = if bin.prim_num > 0 {
+                (bin.offset as u32, bin.prim_num as u32)
+            } else {
+                (self.collapse(bvh, s, scale), 0)
+            };
+            let node = &mut self.nodes[node_id];
+            node.set_box(k, &bin.node_box, scale);
+            node.child[k] = child;
+            node.prim_num[k] = prim_num;
+        }
+
+        node_id as u32
+    }
+
+    // 返回被击中孩子的掩码, 并写入每个孩子的进入距离
+    fn hit_children(
+        &self,
+        node: &WideBvhNode<N>,
+        ray: &WideRay,
+        t_min: f32,
+        t_max: f32,
+        t_near: &mut [f32; 8],
+    ) -> u32 {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            if N == 4 {
+                let node = &*(node as *const WideBvhNode<N> as *const WideBvhNode<4>);
+                return hit_children_sse(node, ray, t_min, t_max, t_near);
+            }
+            if N == 8 && self.use_avx {
+                let node = &*(node as *const WideBvhNode<N> as *const WideBvhNode<8>);
+                return hit_children_avx(node, ray, t_min, t_max, t_near);
+            }
+        }
+        hit_children_scalar(node, ray, t_min, t_max, t_near)
+    }
+}
+
+fn hit_children_scalar<const N: usize>(
+    node: &WideBvhNode<N>,
+    ray: &WideRay,
+    t_min: f32,
+    t_max: f32,
+    t_near: &mut [f32; 8],
+) -> u32 {
+    let (near_x, far_x) = if ray.dir_is_neg[0] {
+        (&node.max_x, &node.min_x)
+    } else {
+        (&node.min_x, &node.max_x)
+    };
+    let (near_y, far_y) = if ray.dir_is_neg[1] {
+        (&node.max_y, &node.min_y)
+    } else {
+        (&node.min_y, &node.max_y)
+    };
+    let (near_z, far_z) = if ray.dir_is_neg[2] {
+        (&node.max_z, &node.min_z)
+    } else {
+        (&node.min_z, &node.max_z)
+    };
+
+    let mut mask = 0;
+    for i in 0..N {
+        let t0 = t_min
+            .max((near_x[i] - ray.orig[0]) * ray.inv_dir[0])
+            .max((near_y[i] - ray.orig[1]) * ray.inv_dir[1])
+            .max((near_z[i] - ray.orig[2]) * ray.inv_dir[2]);
+        let t1 = t_max
+            .min((far_x[i] - ray.orig[0]) * ray.inv_dir[0])
+            .min((far_y[i] - ray.orig[1]) * ray.inv_dir[1])
+            .min((far_z[i] - ray.orig[2]) * ray.inv_dir[2]);
+        t_near[i] = t0;
+        if t0 <= t1 {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn slab_sse(lo: &[f32; 4], hi: &[f32; 4], ray: &WideRay, axis: usize) -> (__m128, __m128) {
+    let (near, far) = if ray.dir_is_neg[axis] {
+        (hi, lo)
+    } else {
+        (lo, hi)
+    };
+    let orig = _mm_set1_ps(ray.orig[axis]);
+    let inv_dir = _mm_set1_ps(ray.inv_dir[axis]);
+    (
+        _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(near.as_ptr()), orig), inv_dir),
+        _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(far.as_ptr()), orig), inv_dir),
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn hit_children_sse(
+    node: &WideBvhNode<4>,
+    ray: &WideRay,
+    t_min: f32,
+    t_max: f32,
+    t_near: &mut [f32; 8],
+) -> u32 {
+    let (t0x, t1x) = slab_sse(&node.min_x, &node.max_x, ray, 0);
+    let (t0y, t1y) = slab_sse(&node.min_y, &node.max_y, ray, 1);
+    let (t0z, t1z) = slab_sse(&node.min_z, &node.max_z, ray, 2);
+
+    let t0 = _mm_max_ps(_mm_max_ps(t0x, t0y), _mm_max_ps(t0z, _mm_set1_ps(t_min)));
+    let t1 = _mm_min_ps(_mm_min_ps(t1x, t1y), _mm_min_ps(t1z, _mm_set1_ps(t_max)));
+
+    _mm_storeu_ps(t_near.as_mut_ptr(), t0);
+    _mm_movemask_ps(_mm_cmple_ps(t0, t1)) as u32
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+#[inline]
+unsafe fn slab_avx(lo: &[f32; 8], hi: &[f32; 8], ray: &WideRay, axis: usize) -> (__m256, __m256) {
+    let (near, far) = if ray.dir_is_neg[axis] {
+        (hi, lo)
+    } else {
+        (lo, hi)
+    };
+    let orig = _mm256_set1_ps(ray.orig[axis]);
+    let inv_dir = _mm256_set1_ps(ray.inv_dir[axis]);
+    (
+        _mm256_mul_ps(_mm256_sub_ps(_mm256_loadu_ps(near.as_ptr()), orig), inv_dir),
+        _mm256_mul_ps(_mm256_sub_ps(_mm256_loadu_ps(far.as_ptr()), orig), inv_dir),
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn hit_children_avx(
+    node: &WideBvhNode<8>,
+    ray: &WideRay,
+    t_min: f32,
+    t_max: f32,
+    t_near: &mut [f32; 8],
+) -> u32 {
+    let (t0x, t1x) = slab_avx(&node.min_x, &node.max_x, ray, 0);
+    let (t0y, t1y) = slab_avx(&node.min_y, &node.max_y, ray, 1);
+    let (t0z, t1z) = slab_avx(&node.min_z, &node.max_z, ray, 2);
+
+    let t0 = _mm256_max_ps(
+        _mm256_max_ps(t0x, t0y),
+        _mm256_max_ps(t0z, _mm256_set1_ps(t_min)),
+    );
+    let t1 = _mm256_min_ps(
+        _mm256_min_ps(t1x, t1y),
+        _mm256_min_ps(t1z, _mm256_set1_ps(t_max)),
+    );
+
+    _mm256_storeu_ps(t_near.as_mut_ptr(), t0);
+    _mm256_movemask_ps(_mm256_cmp_ps(t0, t1, _CMP_LE_OQ)) as u32
+}
+
+impl WideBvh8 {
+    pub fn avx_available() -> bool {
+        WideBvh8::detect_avx()
+    }
+}
+
+impl<const N: usize> WideBvh<N> {
+    fn traverse(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        stack: &mut [StackEntry],
+    ) -> Option<HitRecord> {
+        let ray = WideRay::new(r);
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+
+        stack[0].t_near = f32::NEG_INFINITY;
+        let mut stack_top = 1;
+        let mut t_near = [0.0; 8];
+
+        while stack_top > 0 {
+            stack_top -= 1;
+            let entry = stack[stack_top];
+            if entry.t_near as f64 > closest_so_far {
+                continue;
+            }
+
+            if entry.prim_num > 0 {
+                let first = entry.child as usize;
+                for obj in self.objects[first..first + entry.prim_num as usize].iter() {
+                    if let Some(rec) = obj.hit(r, t_min, closest_so_far) {
+                        closest_so_far = rec.t;
+                        hit_record = Some(rec);
+                    }
+                }
+                continue;
+            }
+
+            let node = &self.nodes[entry.child as usize];
+            let mask = self.hit_children(
+                node,
+                &ray,
+                t_min as f32,
+                // 放宽一点f32下的远距离, 避免因舍入误差漏掉
+                closest_so_far as f32 * 1.000_001,
+                &mut t_near,
+            );
+
+            // 按进入距离从远到近压栈, 近的孩子先被弹出
+            let base = stack_top;
+            for i in 0..N {
+                if mask & (1 << i) == 0 {
+                    continue;
+                }
+                let new_entry = StackEntry {
+                    child: node.child[i],
+                    prim_num: node.prim_num[i],
+                    t_near: t_near[i],
+                };
+                let mut j = stack_top;
+                while j > base && stack[j - 1].t_near < new_entry.t_near {
+                    stack[j] = stack[j - 1];
+                    j -= 1;
+                }
+                stack[j] = new_entry;
+                stack_top += 1;
+            }
+        }
+
+        hit_record
+    }
+}
+
+impl<const N: usize> Hittable for WideBvh<N> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        if self.stack_size <= MAX_STACK_SIZE {
+            let mut stack = [StackEntry::default(); MAX_STACK_SIZE];
+            self.traverse(r, t_min, t_max, &mut stack)
+        } else {
+            let mut stack = vec![StackEntry::default(); self.stack_size];
+            self.traverse(r, t_min, t_max, &mut stack)
+        }
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        if self.nodes.is_empty() {
+            None
+        } else {
+            Some(self.node_box)
+        }
+    }
+}
@@ -81,6 +81,7 @@ impl<T: Material + Sync + Send> Hittable for XYRect<T> {
             mat_ptr: &self.mat,
             t,
             front_face: bool::default(),
+            medium: None,
             normal: Vec3::default(),
         };
         let outward_normal = Vec3::new(0.0, 0.0, 1.0);
@@ -88,11 +89,11 @@ impl<T: Material + Sync + Send> Hittable for XYRect<T> {
         Some(rec)
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(*orig, *v, 0.0), 0.001, INFINITY) {
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(&Ray::new(*orig, *v, time), 0.001, f64::INFINITY) {
             let area = ((self.x1 - self.x0) * (self.y1 - self.y0)).abs();
             let distance_squared = rec.t.powi(2) * v.length_squared();
-            let cosine = Vec3::dot(&v, &rec.normal).abs() / v.length();
+            let cosine = Vec3::dot(v, &rec.normal).abs() / v.length();
 
             distance_squared / (cosine * area)
         } else {
@@ -100,7 +101,7 @@ impl<T: Material + Sync + Send> Hittable for XYRect<T> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         let mut rng = rand::thread_rng();
         Point3::new(
             rng.gen_range(self.x0..self.x1),
@@ -108,6 +109,10 @@ impl<T: Material + Sync + Send> Hittable for XYRect<T> {
             self.z,
         ) - orig
     }
+
+    fn power(&self) -> f64 {
+        ((self.x1 - self.x0) * (self.y1 - self.y0)).abs() * PI * self.mat.emitted_radiance()
+    }
 }
 
 ////////////////////////////////xz_rect//////////////////////////////
@@ -161,6 +166,7 @@ impl<T: Material + Sync + Send> Hittable for XZRect<T> {
             mat_ptr: &self.mat,
             t,
             front_face: bool::default(),
+            medium: None,
             normal: Vec3::default(),
         };
         let outward_normal = Vec3::new(0.0, 1.0, 0.0);
@@ -168,11 +174,11 @@ impl<T: Material + Sync + Send> Hittable for XZRect<T> {
         Some(rec)
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(*orig, *v, 0.0), 0.001, INFINITY) {
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(&Ray::new(*orig, *v, time), 0.001, f64::INFINITY) {
             let area = ((self.x1 - self.x0) * (self.z1 - self.z0)).abs();
             let distance_squared = rec.t.powi(2) * v.length_squared();
-            let cosine = Vec3::dot(&v, &rec.normal).abs() / v.length();
+            let cosine = Vec3::dot(v, &rec.normal).abs() / v.length();
 
             distance_squared / (cosine * area)
         } else {
@@ -180,7 +186,7 @@ impl<T: Material + Sync + Send> Hittable for XZRect<T> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         let mut rng = rand::thread_rng();
         Point3::new(
             rng.gen_range(self.x0..self.x1),
@@ -188,6 +194,10 @@ impl<T: Material + Sync + Send> Hittable for XZRect<T> {
             rng.gen_range(self.z0..self.z1),
         ) - orig
     }
+
+    fn power(&self) -> f64 {
+        ((self.x1 - self.x0) * (self.z1 - self.z0)).abs() * PI * self.mat.emitted_radiance()
+    }
 }
 
 ////////////////////////////////yz_rect//////////////////////////////
@@ -241,6 +251,7 @@ impl<T: Material + Sync + Send> Hittable for YZRect<T> {
             mat_ptr: &self.mat,
             t,
             front_face: bool::default(),
+            medium: None,
             normal: Vec3::default(),
         };
         let outward_normal = Vec3::new(1.0, 0.0, 0.0);
@@ -248,11 +259,11 @@ impl<T: Material + Sync + Send> Hittable for YZRect<T> {
         Some(rec)
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(*orig, *v, 0.0), 0.001, INFINITY) {
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(&Ray::new(*orig, *v, time), 0.001, f64::INFINITY) {
             let area = ((self.y1 - self.y0) * (self.z1 - self.z0)).abs();
             let distance_squared = rec.t.powi(2) * v.length_squared();
-            let cosine = Vec3::dot(&v, &rec.normal).abs() / v.length();
+            let cosine = Vec3::dot(v, &rec.normal).abs() / v.length();
 
             distance_squared / (cosine * area)
         } else {
@@ -260,7 +271,7 @@ impl<T: Material + Sync + Send> Hittable for YZRect<T> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         let mut rng = rand::thread_rng();
         Point3::new(
             self.x,
@@ -268,4 +279,8 @@ impl<T: Material + Sync + Send> Hittable for YZRect<T> {
             rng.gen_range(self.z0..self.z1),
         ) - orig
     }
+
+    fn power(&self) -> f64 {
+        ((self.y1 - self.y0) * (self.z1 - self.z0)).abs() * PI * self.mat.emitted_radiance()
+    }
 }
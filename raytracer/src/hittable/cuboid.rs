@@ -70,8 +70,12 @@ impl<T: Material + Sync + Send> Hittable for Cuboid<T> {
         let mut opt_rec = None;
         let mut closest_so_far = t_max;
 
-        for side in self.sides.iter() {
-            if let Some(temp_rec) = side.hit(&r, t_min, closest_so_far) {
+        for (i, side) in self.sides.iter().enumerate() {
+            if let Some(mut temp_rec) = side.hit(r, t_min, closest_so_far) {
+                // 坐标较小的三个面法线朝向内部, 翻转后所有面的正面都朝外, 折射和介质才能分清内外
+                if i % 2 == 1 {
+                    temp_rec.front_face = !temp_rec.front_face;
+                }
                 closest_so_far = temp_rec.t;
                 opt_rec = Some(temp_rec);
             }
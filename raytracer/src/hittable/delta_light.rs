@@ -0,0 +1,208 @@
+use super::{HitRecord, Hittable, LightSample};
+use crate::basic::ies::IesProfile;
+use crate::basic::onb::ONB;
+use crate::bvh::aabb::AABB;
+use crate::utility::*;
+
+use std::sync::Arc;
+
+// 这些光源没有几何形状, 光线永远打不到, 只能在着色点处显式采样,
+// 因此应当放在lights列表里, 而不是world里
+
+////////////////////////////////point_light////////////////////////////////
+
+#[derive(Clone)]
+pub struct PointLight {
+    position: Point3,
+    intensity: Color, // 辐射强度, 距离为d处的辐照度为 intensity / d^2
+    profile: Option<(Arc<IesProfile>, Vec3, Vec3)>, // 配光曲线, 灯具主轴和水平角0度的方向
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            profile: None,
+        }
+    }
+
+    // 按IES配光曲线调制各方向的光强, 此时intensity为最大光强
+    // 灯具主轴axis通常朝下, 即 (0, -1, 0), up在垂直于主轴的平面上的投影为水平角0度
+    pub fn with_ies_profile(mut self, profile: IesProfile, axis: Vec3, up: Vec3) -> Self {
+        self.profile = Some((Arc::new(profile), axis.unit_vector(), up));
+        self
+    }
+}
+
+impl Hittable for PointLight {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        None
+    }
+
+    fn power(&self) -> f64 {
+        4.0 * PI * self.intensity.luminance()
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample_li(&self, p: &Point3, time: f64) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let dist = to_light.length();
+        let dir = to_light / dist;
+        let intensity = match &self.profile {
+            Some((profile, axis, up)) => self.intensity * profile.value_in_dir(axis, up, &-dir),
+            None => self.intensity,
+        };
+        Some(LightSample {
+            dir,
+            dist,
+            radiance: intensity / (dist * dist),
+        })
+    }
+}
+
+////////////////////////////////spot_light////////////////////////////////
+
+#[derive(Clone)]
+pub struct SpotLight {
+    position: Point3,
+    dir: Vec3, // 聚光灯的朝向
+    intensity: Color,
+    cos_total_width: f64,   // 光锥边缘
+    cos_falloff_start: f64, // 从这里开始衰减
+}
+
+impl SpotLight {
+    // total_width和falloff_start都是与朝向的夹角, 单位为度
+    pub fn new(
+        position: Point3,
+        look_at: Point3,
+        intensity: Color,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        Self {
+            position,
+            dir: (look_at - position).unit_vector(),
+            intensity,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.min(total_width).to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta < self.cos_total_width {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            let delta = (cos_theta - self.cos_total_width)
+                / (self.cos_falloff_start - self.cos_total_width);
+            delta.powi(4)
+        }
+    }
+}
+
+impl Hittable for SpotLight {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        None
+    }
+
+    fn power(&self) -> f64 {
+        // 按光锥中间的位置近似衰减区域
+        2.0 * PI
+            * (1.0 - 0.5 * (self.cos_falloff_start + self.cos_total_width))
+            * self.intensity.luminance()
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample_li(&self, p: &Point3, time: f64) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let dist = to_light.length();
+        let dir = to_light / dist;
+        let falloff = self.falloff(Vec3::dot(&-dir, &self.dir));
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            dir,
+            dist,
+            radiance: self.intensity * falloff / (dist * dist),
+        })
+    }
+}
+
+////////////////////////////////directional_light////////////////////////////////
+
+#[derive(Clone)]
+pub struct DirectionalLight {
+    dir: Vec3,          // 指向光源的方向
+    irradiance: Color,  // 垂直于光线的平面上的辐照度
+    cos_theta_max: f64, // 光源张角的一半, 为1时是理想的平行光
+}
+
+impl DirectionalLight {
+    // dir是光线传播的方向
+    pub fn new(dir: Vec3, irradiance: Color) -> Self {
+        DirectionalLight::new_with_angle(dir, irradiance, 0.0)
+    }
+
+    // angular_diameter是光源的视直径(度), 例如太阳约为0.53, 用来产生软阴影
+    pub fn new_with_angle(dir: Vec3, irradiance: Color, angular_diameter: f64) -> Self {
+        Self {
+            dir: -dir.unit_vector(),
+            irradiance,
+            cos_theta_max: (angular_diameter / 2.0).to_radians().cos(),
+        }
+    }
+}
+
+impl Hittable for DirectionalLight {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        None
+    }
+
+    // 功率与场景大小有关, 这里只给一个与辐照度成正比的权重
+    fn power(&self) -> f64 {
+        self.irradiance.luminance()
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    // 在光源张成的锥内均匀取方向, 辐亮度为 E / Ω, 除以pdf 1 / Ω后恰好为 E
+    fn sample_li(&self, p: &Point3, time: f64) -> Option<LightSample> {
+        let dir = if self.cos_theta_max < 1.0 {
+            let sin_theta_max_sqrd = 1.0 - self.cos_theta_max.powi(2);
+            ONB::build_from_w(self.dir)
+                .local(Vec3::rand_to_sphere(sin_theta_max_sqrd.sqrt(), 1.0))
+                .unit_vector()
+        } else {
+            self.dir
+        };
+        Some(LightSample {
+            dir,
+            dist: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+}
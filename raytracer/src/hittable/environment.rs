@@ -0,0 +1,248 @@
+use super::{HitRecord, Hittable};
+use crate::basic::background::Background;
+use crate::basic::mat4::Mat4;
+use crate::bvh::aabb::AABB;
+use crate::pdf::distribution::Distribution2D;
+use crate::utility::exr::read_exr;
+use crate::utility::*;
+
+use console::style;
+use image::codecs::hdr::{HdrDecoder, HdrEncoder};
+use image::{ColorType, Rgb};
+use rand::Rng;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+// 等距柱状投影(equirectangular)的环境贴图, 既是背景, 也是可以重要性采样的无穷远光源
+// 需要同时作为背景传给渲染器, 并加入lights列表
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+    intensity: f64,
+    rotation: Mat4, // 贴图空间到世界空间
+    inv_rotation: Mat4,
+    distribution: Distribution2D,
+    average_luminance: f64,
+}
+
+impl EnvironmentLight {
+    // rotation为绕y轴旋转的角度(度)
+    pub fn new_from_file(filename: &str, rotation: f64, intensity: f64) -> Self {
+        let (width, height, pixels) = EnvironmentLight::load_image(filename);
+        EnvironmentLight::new(width, height, pixels, rotation, intensity)
+    }
+
+    // 把任意背景(如解析的天空模型)烘焙成贴图, 以便重要性采样
+    pub fn new_from_background(bg: &dyn Background, width: usize, height: usize) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for j in 0..height {
+            for i in 0..width {
+                let dir = EnvironmentLight::uv_to_dir(
+                    (i as f64 + 0.5) / width as f64,
+                    (j as f64 + 0.5) / height as f64,
+                );
+                let color = bg.value(&dir);
+                pixels.push([color.x as f32, color.y as f32, color.z as f32]);
+            }
+        }
+        EnvironmentLight::new(width, height, pixels, 0.0, 1.0)
+    }
+
+    fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<[f32; 3]>,
+        rotation: f64,
+        intensity: f64,
+    ) -> Self {
+        // 每个像素的亮度乘上sinθ, 抵消两极处像素对应的立体角更小
+        let mut func = Vec::with_capacity(width * height);
+        let mut average_luminance = 0.0;
+        for j in 0..height {
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let luminance = EnvironmentLight::to_color(&pixels[j * width + i]).luminance();
+                func.push(luminance * sin_theta);
+                average_luminance += luminance * sin_theta;
+            }
+        }
+        // 按立体角加权的平均值
+        average_luminance *= PI / (2.0 * (width * height) as f64);
+
+        let rotation = Mat4::rotation_y(rotation);
+        Self {
+            distribution: Distribution2D::new(&func, width, height),
+            width,
+            height,
+            pixels,
+            intensity,
+            inv_rotation: rotation.inverse().unwrap(),
+            rotation,
+            average_luminance,
+        }
+    }
+
+    // .hdr和.exr按浮点读入, 普通图片(如images/earthmap.jpg)按 [0, 1] 读入
+    fn load_image(filename: &str) -> (usize, usize, Vec<[f32; 3]>) {
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("hdr") => {
+                let file = match File::open(filename) {
+                    Ok(file) => file,
+                    Err(_) => panic!("Couldn't open file: {}", filename),
+                };
+                let decoder = match HdrDecoder::new(BufReader::new(file)) {
+                    Ok(decoder) => decoder,
+                    Err(_) => panic!("Couldn't decode HDR file: {}", filename),
+                };
+                let meta = decoder.metadata();
+                let pixels = match decoder.read_image_hdr() {
+                    Ok(pixels) => pixels.into_iter().map(|p| p.0).collect(),
+                    Err(_) => panic!("Couldn't decode HDR file: {}", filename),
+                };
+                (meta.width as usize, meta.height as usize, pixels)
+            }
+            Some("exr") => match read_exr(filename) {
+                Ok(image) => image,
+                Err(err) => panic!("Couldn't decode OpenEXR file {}: {}", filename, err),
+            },
+            _ => {
+                let img = match image::open(filename) {
+                    Ok(img) => img,
+                    Err(_) => panic!("Couldn't open file: {}", filename),
+                }
+                .into_rgb8();
+                let color_scale = 1.0 / 255.0;
+                let pixels = img
+                    .pixels()
+                    .map(|p| {
+                        [
+                            p[0] as f32 * color_scale,
+                            p[1] as f32 * color_scale,
+                            p[2] as f32 * color_scale,
+                        ]
+                    })
+                    .collect();
+                (img.width() as usize, img.height() as usize, pixels)
+            }
+        }
+    }
+
+    // 导出贴图便于调试, .hdr保存原始数值, 其他格式与渲染结果一样做gamma校正
+    pub fn save_to_file(&self, filename: &str) {
+        let is_hdr = matches!(
+            Path::new(filename).extension(),
+            Some(ext) if ext.eq_ignore_ascii_case("hdr")
+        );
+        let scaled = self
+            .pixels
+            .iter()
+            .map(|p| EnvironmentLight::to_color(p) * self.intensity);
+
+        let result = if is_hdr {
+            let data: Vec<Rgb<f32>> = scaled
+                .map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32]))
+                .collect();
+            match File::create(filename) {
+                Ok(file) => {
+                    HdrEncoder::new(BufWriter::new(file)).encode(&data, self.width, self.height)
+                }
+                Err(_) => panic!("Couldn't create file: {}", filename),
+            }
+        } else {
+            let data: Vec<u8> = scaled
+                .flat_map(|c| c.calc_color(1).to_u8_array().to_vec())
+                .collect();
+            image::save_buffer(
+                filename,
+                &data,
+                self.width as u32,
+                self.height as u32,
+                ColorType::Rgb8,
+            )
+        };
+        if result.is_err() {
+            println!(
+                "  Error: {}{}",
+                style("Failed to save environment map: ").red(),
+                style(filename).yellow()
+            );
+        }
+    }
+
+    fn to_color(pixel: &[f32; 3]) -> Color {
+        Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+    }
+
+    // 贴图空间中的方向对应的 (u, v), v=0 为贴图顶部(+y)
+    // 与Sphere::get_sphere_uv的经度约定相同
+    fn dir_to_uv(dir: &Vec3) -> (f64, f64) {
+        let dir = dir.unit_vector();
+        let theta = dir.y.clamp(-1.0, 1.0).acos();
+        let phi = f64::atan2(-dir.z, dir.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_dir(u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI;
+        Vec3::new(
+            -theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        EnvironmentLight::to_color(&self.pixels[j * self.width + i]) * self.intensity
+    }
+}
+
+impl Background for EnvironmentLight {
+    fn value(&self, dir: &Vec3) -> Color {
+        let (u, v) = EnvironmentLight::dir_to_uv(&self.inv_rotation.transform_vector(*dir));
+        self.lookup(u, v)
+    }
+}
+
+impl Hittable for EnvironmentLight {
+    // 无穷远处的光源, 光线没有击中任何物体时由背景给出它的颜色
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        None
+    }
+
+    // (u, v) 上的密度换算到立体角: dω = 2π^2 sinθ du dv
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        let (u, v) = EnvironmentLight::dir_to_uv(&self.inv_rotation.transform_vector(*v));
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let ((u, v), _) = self
+            .distribution
+            .sample_continuous(rng.gen::<f64>(), rng.gen::<f64>());
+        self.rotation
+            .transform_vector(EnvironmentLight::uv_to_dir(u, v))
+    }
+
+    // 功率与场景大小有关, 这里按照射单位面积的通量给一个选择权重
+    fn power(&self) -> f64 {
+        PI * self.average_luminance * self.intensity
+    }
+}
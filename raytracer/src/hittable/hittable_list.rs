@@ -1,23 +1,113 @@
-use rand::prelude::SliceRandom;
+use rand::distributions::{Distribution, WeightedIndex};
 
 use super::{HitRecord, Hittable};
+use crate::basic::mat4::Mat4;
 use crate::bvh::aabb::AABB;
+use crate::medium::Medium;
 use crate::utility::*;
 use std::sync::Arc;
 
 #[derive(Default, Clone)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
+    pub medium: Option<Arc<dyn Medium>>, // 作为场景时充满整个场景的介质, 如雾
+    light_sampler: Option<LightSampler>,
+}
+
+// 作为光源列表时各光源的选择权重
+#[derive(Clone)]
+struct LightSampler {
+    weights: Vec<f64>,
+    total: f64,
+    dist: Option<WeightedIndex<f64>>, // 没有能按方向采样的光源时为None
+}
+
+impl LightSampler {
+    fn new(list: &HittableList) -> Self {
+        let weights = list.light_weights();
+        let total = weights.iter().sum();
+        let dist = if total > 0.0 {
+            WeightedIndex::new(&weights).ok()
+        } else {
+            None
+        };
+        Self {
+            weights,
+            total,
+            dist,
+        }
+    }
 }
 
 #[allow(unused)]
 impl HittableList {
     pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
+        self.light_sampler = None;
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.light_sampler = None;
+    }
+
+    pub fn set_medium(&mut self, medium: Arc<dyn Medium>) {
+        self.medium = Some(medium);
+    }
+
+    // 作为光源列表时各物体被选中的权重, 与功率成正比
+    // 不发光的物体(如只用来引导采样的玻璃球)取平均功率, 全都不发光时退化为均匀选择
+    // 点光源和空网格等不能按方向采样, 权重为0
+    pub fn light_weights(&self) -> Vec<f64> {
+        let powers: Vec<f64> = self
+            .objects
+            .iter()
+            .map(|obj| {
+                if obj.can_sample_direction() {
+                    obj.power()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let emitters = powers.iter().filter(|&&p| p > 0.0).count();
+        let fallback = if emitters == 0 {
+            1.0
+        } else {
+            powers.iter().sum::<f64>() / emitters as f64
+        };
+        self.objects
+            .iter()
+            .zip(powers)
+            .map(|(obj, p)| {
+                if !obj.can_sample_direction() {
+                    0.0
+                } else if p > 0.0 {
+                    p
+                } else {
+                    fallback
+                }
+            })
+            .collect()
+    }
+
+    // 光源列表建好后计算一次选择权重, 渲染时直接使用
+    // add和clear会清掉权重, 直接修改objects后需要重新调用
+    pub fn build_light_sampler(&mut self) {
+        self.light_sampler = Some(LightSampler::new(self));
+    }
+
+    // 没有调用过build_light_sampler的列表(如嵌套在光源列表里的列表)每次临时计算权重
+    fn with_light_sampler<R>(&self, f: impl FnOnce(&LightSampler) -> R) -> R {
+        match &self.light_sampler {
+            Some(sampler) => f(sampler),
+            None => f(&LightSampler::new(self)),
+        }
+    }
+
+    // 是否有能按方向采样的光源
+    pub fn has_area_lights(&self) -> bool {
+        self.objects.iter().any(|obj| obj.can_sample_direction())
     }
 }
 
@@ -60,20 +150,48 @@ impl Hittable for HittableList {
         Some(output_box)
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        let weight = 1. / self.objects.len() as f64;
-        let mut ave_pdf = 0.0;
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        let mut boxes = self
+            .objects
+            .iter()
+            .map(|object| object.transformed_box(m, st_time, ed_time));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |output_box, tmp_box| {
+            Some(AABB::surrounding_box(&output_box, &tmp_box?))
+        })
+    }
 
-        for obj in &self.objects {
-            ave_pdf += obj.pdf_value(orig, v) * weight;
+    // 按功率加权选择光源
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.with_light_sampler(|sampler| {
+            if sampler.total == 0.0 {
+                return 0.0;
+            }
+            let mut ave_pdf = 0.0;
+            for (obj, weight) in self.objects.iter().zip(sampler.weights.iter()) {
+                if *weight > 0.0 {
+                    ave_pdf += obj.pdf_value(orig, v, time) * weight / sampler.total;
+                }
+            }
+            ave_pdf
+        })
+    }
+
+    // 没有能按方向采样的光源时返回任意方向, 它的pdf_value为0
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        let idx = self.with_light_sampler(|sampler| {
+            sampler
+                .dist
+                .as_ref()
+                .map(|dist| dist.sample(&mut rand::thread_rng()))
+        });
+        match idx {
+            Some(idx) => self.objects[idx].random(orig, time),
+            None => Vec3::new(1.0, 0.0, 0.0),
         }
-        ave_pdf
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
-        self.objects
-            .choose(&mut rand::thread_rng())
-            .unwrap()
-            .random(orig)
+    fn power(&self) -> f64 {
+        self.objects.iter().map(|obj| obj.power()).sum()
     }
 }
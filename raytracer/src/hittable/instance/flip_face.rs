@@ -24,4 +24,20 @@ impl<T: Hittable> Hittable for FlipFace<T> {
             None
         }
     }
+
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj.pdf_value(orig, v, time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.obj.random(orig, time)
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
@@ -0,0 +1,69 @@
+use super::transform::Transform;
+use crate::basic::mat4::Mat4;
+use crate::bvh::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::utility::*;
+
+use std::sync::Arc;
+
+// 共享的底层加速结构(BLAS), 例如一个ObjModel, 多个实例引用同一份几何
+pub type MeshHandle = Arc<dyn Hittable>;
+
+// 网格的一个实例, 只保存变换和可选的替换材质
+pub struct MeshInstance {
+    mesh: Transform<MeshHandle>,
+    mat: Option<Arc<dyn Material + Send + Sync>>, // 为空时使用网格自己的材质
+}
+
+impl MeshInstance {
+    pub fn new(mesh: MeshHandle, transform: Mat4) -> Self {
+        Self {
+            mesh: Transform::new(mesh, transform),
+            mat: None,
+        }
+    }
+
+    pub fn with_material<T>(mut self, mat: T) -> Self
+    where
+        T: Material + Send + Sync + 'static,
+    {
+        self.mat = Some(Arc::new(mat));
+        self
+    }
+}
+
+impl Hittable for MeshInstance {
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.mesh.bounding_box(st_time, ed_time)
+    }
+
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.mesh.transformed_box(m, st_time, ed_time)
+    }
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.mesh.hit(r, t_min, t_max).map(|mut rec| {
+            if let Some(mat) = &self.mat {
+                rec.mat_ptr = mat.as_ref();
+            }
+            rec
+        })
+    }
+
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.mesh.pdf_value(orig, v, time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.mesh.random(orig, time)
+    }
+
+    fn power(&self) -> f64 {
+        self.mesh.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.mesh.can_sample_direction()
+    }
+}
@@ -1,4 +1,6 @@
 pub mod flip_face;
+pub mod mesh_instance;
 pub mod motion;
 pub mod rotation;
+pub mod transform;
 pub mod translation;
@@ -18,16 +18,19 @@ impl<T: Hittable> Motion<T> {
             ed_time,
         }
     }
+
+    fn offset(&self, time: f64) -> Vec3 {
+        (time - self.st_time) / (self.ed_time - self.st_time) * self.mov
+    }
 }
 
 impl<T: Hittable> Hittable for Motion<T> {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let offset = (r.tm - self.st_time) / (self.ed_time - self.st_time) * self.mov;
+        let offset = self.offset(r.tm);
         // 根据相对运动，移动光线来替代移动物体
         let tmp_ray = Ray::new(r.orig - offset, r.dir, r.tm);
         if let Some(mut rec) = self.obj.hit(&tmp_ray, t_min, t_max) {
             rec.p += offset; // 将光线的移动还原
-            rec.set_face_normal(&tmp_ray, rec.normal);
             Some(rec)
         } else {
             None
@@ -39,4 +42,21 @@ impl<T: Hittable> Hittable for Motion<T> {
         let box1 = AABB::new(box0.minimum + self.mov, box0.maximum + self.mov);
         Some(AABB::surrounding_box(&box0, &box1))
     }
+
+    // 光源采样时同样用光线的时间确定物体的位置
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj.pdf_value(&(*orig - self.offset(time)), v, time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.obj.random(orig - self.offset(time), time)
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
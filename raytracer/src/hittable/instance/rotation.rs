@@ -15,8 +15,8 @@ impl<T: Hittable> RotationY<T> {
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let opt_box = if let Some(bbox) = obj.bounding_box(0.0, 1.0) {
-            let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-            let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+            let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+            let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
             for i in 0..2 {
                 for j in 0..2 {
@@ -51,6 +51,21 @@ impl<T: Hittable> RotationY<T> {
             opt_box,
         }
     }
+
+    // 世界空间的向量转到物体的局部空间
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        let mut local = v;
+        local[0] = self.cos_theta * v[0] - self.sin_theta * v[2];
+        local[2] = self.sin_theta * v[0] + self.cos_theta * v[2];
+        local
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        let mut world = v;
+        world[0] = self.cos_theta * v[0] + self.sin_theta * v[2];
+        world[2] = -self.sin_theta * v[0] + self.cos_theta * v[2];
+        world
+    }
 }
 
 impl<T: Hittable> Hittable for RotationY<T> {
@@ -81,13 +96,32 @@ impl<T: Hittable> Hittable for RotationY<T> {
             normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
 
             rec.p = p;
-            rec.set_face_normal(&rotated_r, normal);
+            // 旋转不改变法线与光线的相对朝向, front_face保持原样
+            rec.normal = normal;
 
             Some(rec)
         } else {
             None
         }
     }
+
+    // 旋转是刚体变换, 立体角不变, 直接在局部空间采样即可
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj
+            .pdf_value(&self.to_local(*orig), &self.to_local(*v), time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.to_world(self.obj.random(self.to_local(orig), time))
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
 
 pub struct RotationX<T> {
@@ -103,8 +137,8 @@ impl<T: Hittable> RotationX<T> {
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let opt_box = if let Some(bbox) = obj.bounding_box(0.0, 1.0) {
-            let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-            let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+            let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+            let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
             for i in 0..2 {
                 for j in 0..2 {
@@ -139,6 +173,21 @@ impl<T: Hittable> RotationX<T> {
             opt_box,
         }
     }
+
+    // 世界空间的向量转到物体的局部空间
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        let mut local = v;
+        local[2] = self.cos_theta * v[2] - self.sin_theta * v[1];
+        local[1] = self.sin_theta * v[2] + self.cos_theta * v[1];
+        local
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        let mut world = v;
+        world[2] = self.cos_theta * v[2] + self.sin_theta * v[1];
+        world[1] = -self.sin_theta * v[2] + self.cos_theta * v[1];
+        world
+    }
 }
 
 impl<T: Hittable> Hittable for RotationX<T> {
@@ -169,13 +218,32 @@ impl<T: Hittable> Hittable for RotationX<T> {
             normal[1] = -self.sin_theta * rec.normal[2] + self.cos_theta * rec.normal[1];
 
             rec.p = p;
-            rec.set_face_normal(&rotated_r, normal);
+            // 旋转不改变法线与光线的相对朝向, front_face保持原样
+            rec.normal = normal;
 
             Some(rec)
         } else {
             None
         }
     }
+
+    // 旋转是刚体变换, 立体角不变, 直接在局部空间采样即可
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj
+            .pdf_value(&self.to_local(*orig), &self.to_local(*v), time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.to_world(self.obj.random(self.to_local(orig), time))
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
 
 pub struct RotationZ<T> {
@@ -191,8 +259,8 @@ impl<T: Hittable> RotationZ<T> {
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let opt_box = if let Some(bbox) = obj.bounding_box(0.0, 1.0) {
-            let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-            let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+            let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+            let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
             for i in 0..2 {
                 for j in 0..2 {
@@ -227,6 +295,21 @@ impl<T: Hittable> RotationZ<T> {
             opt_box,
         }
     }
+
+    // 世界空间的向量转到物体的局部空间
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        let mut local = v;
+        local[1] = self.cos_theta * v[1] - self.sin_theta * v[0];
+        local[0] = self.sin_theta * v[1] + self.cos_theta * v[0];
+        local
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        let mut world = v;
+        world[1] = self.cos_theta * v[1] + self.sin_theta * v[0];
+        world[0] = -self.sin_theta * v[1] + self.cos_theta * v[0];
+        world
+    }
 }
 
 impl<T: Hittable> Hittable for RotationZ<T> {
@@ -257,11 +340,30 @@ impl<T: Hittable> Hittable for RotationZ<T> {
             normal[0] = -self.sin_theta * rec.normal[1] + self.cos_theta * rec.normal[0];
 
             rec.p = p;
-            rec.set_face_normal(&rotated_r, normal);
+            // 旋转不改变法线与光线的相对朝向, front_face保持原样
+            rec.normal = normal;
 
             Some(rec)
         } else {
             None
         }
     }
+
+    // 旋转是刚体变换, 立体角不变, 直接在局部空间采样即可
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj
+            .pdf_value(&self.to_local(*orig), &self.to_local(*v), time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.to_world(self.obj.random(self.to_local(orig), time))
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
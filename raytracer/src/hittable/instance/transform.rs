@@ -0,0 +1,106 @@
+use crate::basic::mat4::Mat4;
+use crate::bvh::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::utility::*;
+
+// 任意仿射变换(旋转/缩放/错切/平移及其组合)下的物体
+pub struct Transform<T: Hittable> {
+    obj: T,
+    m: Mat4,
+    inv: Mat4,
+    normal_m: Mat4, // 法线变换矩阵, 即逆矩阵的转置
+    opt_box: Option<AABB>,
+}
+
+impl<T: Hittable> Transform<T> {
+    pub fn new(obj: T, m: Mat4) -> Self {
+        let inv = m.inverse().expect("Transform matrix should be invertible.");
+        let opt_box = obj.transformed_box(&m, 0.0, 1.0);
+        Self {
+            obj,
+            m,
+            inv,
+            normal_m: inv.transpose(),
+            opt_box,
+        }
+    }
+}
+
+impl<T: Hittable> Hittable for Transform<T> {
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.opt_box
+    }
+
+    // 嵌套的变换合并成一个矩阵, 交给最里层的物体计算
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.obj.transformed_box(&(*m * self.m), st_time, ed_time)
+    }
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // 方向不归一化, 局部空间中的t与世界空间相同
+        let local_r = Ray::new(
+            self.inv.transform_point(r.orig),
+            self.inv.transform_vector(r.dir),
+            r.tm,
+        );
+
+        if let Some(mut rec) = self.obj.hit(&local_r, t_min, t_max) {
+            rec.p = self.m.transform_point(rec.p);
+            // 变换后的法线与光线方向的点积符号不变, front_face保持原样
+            rec.normal = self.normal_m.transform_vector(rec.normal).unit_vector();
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    // 非刚体变换会改变立体角, 局部空间的pdf需乘上方向映射的雅可比行列式
+    // 设A为逆矩阵的线性部分, 则 dω_local / dω_world = |det A| / |Aω|^3
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        let local_v = self.inv.transform_vector(v.unit_vector());
+        let local_pdf = self
+            .obj
+            .pdf_value(&self.inv.transform_point(*orig), &local_v, time);
+        if local_pdf == 0.0 {
+            return 0.0;
+        }
+        local_pdf * self.inv.determinant3().abs() / local_v.length().powi(3)
+    }
+
+    // 采样得到的是指向光源上一点的向量, 只需变换回世界空间
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        let local_dir = self.obj.random(self.inv.transform_point(orig), time);
+        self.m.transform_vector(local_dir)
+    }
+
+    // 面积的缩放比例, 只有均匀缩放时是精确的
+    fn power(&self) -> f64 {
+        self.obj.power() * self.m.determinant3().abs().powf(2.0 / 3.0)
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::sphere::Sphere;
+    use crate::material::lambertian::Lambertian;
+
+    #[test]
+    fn rotated_sphere_box_stays_tight() {
+        let mat = Lambertian::new_form_color(Color::new(0.5, 0.5, 0.5));
+        let sphere = Sphere::new(Point3::new(1., 0., 0.), 2., mat);
+        let m = Mat4::translation(Vec3::new(0., 3., 0.))
+            * Mat4::rotation(Vec3::new(1., 1., 1.), 45.)
+            * Mat4::uniform_scale(0.5);
+        let bbox = Transform::new(sphere, m).bounding_box(0.0, 1.0).unwrap();
+        let center = m.transform_point(Point3::new(1., 0., 0.));
+        for i in 0..3 {
+            assert!((bbox.minimum[i] - (center[i] - 1.)).abs() < 1e-9);
+            assert!((bbox.maximum[i] - (center[i] + 1.)).abs() < 1e-9);
+        }
+    }
+}
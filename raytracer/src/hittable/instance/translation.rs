@@ -24,10 +24,25 @@ impl<T: Hittable> Hittable for Translation<T> {
         let moved_r = Ray::new(r.orig - self.offset, r.dir, r.tm);
         if let Some(mut rec) = self.obj.hit(&moved_r, t_min, t_max) {
             rec.p += self.offset;
-            rec.set_face_normal(&moved_r, rec.normal);
             Some(rec)
         } else {
             None
         }
     }
+
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.obj.pdf_value(&(*orig - self.offset), v, time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.obj.random(orig - self.offset, time)
+    }
+
+    fn power(&self) -> f64 {
+        self.obj.power()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.obj.can_sample_direction()
+    }
 }
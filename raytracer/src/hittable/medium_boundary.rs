@@ -0,0 +1,47 @@
+use super::{HitRecord, Hittable};
+use crate::bvh::aabb::AABB;
+use crate::material::null::Null;
+use crate::medium::Medium;
+use crate::utility::*;
+
+// 把介质附着在封闭曲面的内部, 曲面可以是非凸的
+// 积分器根据光线穿过曲面的方向切换所在的介质, 离开时回到场景的全局介质, 因此介质不能嵌套
+// 默认边界不可见, 边界自身的材质被忽略; new_with_surface保留边界的材质, 如装着烟雾的玻璃球
+pub struct MediumBoundary<H: Hittable, M: Medium> {
+    boundary: H,
+    medium: M,
+    visible: bool,
+}
+
+impl<H: Hittable, M: Medium> MediumBoundary<H, M> {
+    pub fn new(boundary: H, medium: M) -> Self {
+        Self {
+            boundary,
+            medium,
+            visible: false,
+        }
+    }
+
+    pub fn new_with_surface(boundary: H, medium: M) -> Self {
+        Self {
+            boundary,
+            medium,
+            visible: true,
+        }
+    }
+}
+
+impl<H: Hittable, M: Medium> Hittable for MediumBoundary<H, M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut rec = self.boundary.hit(r, t_min, t_max)?;
+        rec.medium = Some(&self.medium);
+        if !self.visible {
+            rec.mat_ptr = &Null;
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.boundary.bounding_box(st_time, ed_time)
+    }
+}
@@ -1,16 +1,24 @@
 pub mod aarect;
 pub mod constant_medium;
 pub mod cuboid;
+pub mod delta_light;
+pub mod environment;
 pub mod hittable_list;
 pub mod instance;
+pub mod medium_boundary;
 pub mod obj_model;
 pub mod sphere;
+pub mod subsurface;
 pub mod triangle;
 
+use crate::basic::mat4::Mat4;
 use crate::bvh::aabb::AABB;
 use crate::material::{lambertian::Lambertian, Material};
+use crate::medium::Medium;
 use crate::utility::*;
 
+use std::sync::Arc;
+
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
@@ -20,6 +28,7 @@ pub struct HitRecord<'a> {
     pub u: f64, // texture uv
     pub v: f64,
     pub front_face: bool,
+    pub medium: Option<&'a dyn Medium>, // 曲面内部的介质, 不是介质的边界时为None
 }
 
 impl<'a> HitRecord<'a> {
@@ -33,13 +42,80 @@ impl<'a> HitRecord<'a> {
     }
 }
 
+// 显式采样光源得到的结果
+pub struct LightSample {
+    pub dir: Vec3,       // 从着色点指向光源, 单位向量
+    pub dist: f64,       // 到光源的距离, 用于阴影测试
+    pub radiance: Color, // 到达着色点的辐亮度, 已除以采样的pdf
+}
+
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB>;
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
+    // 经过仿射变换m后的包围盒, 默认取变换后包围盒的范围, 旋转时会偏大
+    // 球, 三角面和网格按变换后的几何计算精确范围
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.bounding_box(st_time, ed_time)
+            .map(|bbox| m.transform_box(&bbox))
+    }
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
         0.0
     }
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    // 发出的总功率(单面的朗伯发光面为 面积 * π * 辐亮度), 用于选择光源
+    fn power(&self) -> f64 {
+        0.0
+    }
+    // 点光源等无法被光线击中的光源, 不参与按方向的重要性采样, 只能用sample_li显式采样
+    fn is_delta(&self) -> bool {
+        false
+    }
+    // 作为光源时能否按方向采样, 点光源和没有面积的网格不能, 不会被选中
+    fn can_sample_direction(&self) -> bool {
+        !self.is_delta()
+    }
+    fn sample_li(&self, p: &Point3, time: f64) -> Option<LightSample> {
+        None
+    }
+}
+
+// 让共享的物体(如实例引用的网格)也能直接作为Hittable使用
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.as_ref().hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.as_ref().bounding_box(st_time, ed_time)
+    }
+
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.as_ref().transformed_box(m, st_time, ed_time)
+    }
+
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        self.as_ref().pdf_value(orig, v, time)
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        self.as_ref().random(orig, time)
+    }
+
+    fn power(&self) -> f64 {
+        self.as_ref().power()
+    }
+
+    fn is_delta(&self) -> bool {
+        self.as_ref().is_delta()
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.as_ref().can_sample_direction()
+    }
+
+    fn sample_li(&self, p: &Point3, time: f64) -> Option<LightSample> {
+        self.as_ref().sample_li(p, time)
+    }
 }
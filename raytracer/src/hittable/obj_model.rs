@@ -3,28 +3,102 @@ use std::sync::Arc;
 use super::hittable_list::HittableList;
 use super::triangle::Triangle;
 use super::{hittable_list, HitRecord, Hittable};
+use crate::basic::mat4::Mat4;
 use crate::bvh::aabb::AABB;
-use crate::bvh::bvh_node::BvhNode;
+use crate::bvh::wide_bvh::{WideBvh4, WideBvh8};
 use crate::material::Material;
 use crate::material::{
     dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian, metal::Metal,
+    principled::Principled,
 };
 use crate::texture::image_texture::ImageTexture;
 use crate::texture::obj_texture::ObjTexture;
 use crate::texture::solid_color::SolidColor;
+use crate::texture::uv_texture::TriangleUV;
 use crate::texture::Texture;
 use crate::utility::*;
 use console::style;
 use image::RgbImage;
 use rand::Rng;
+use std::path::Path;
+use std::time::Instant;
 use tobj;
 
+// 按面积在三角面中选取, 用于把整个网格当作面光源采样
+struct AreaSampler {
+    tris: Vec<Arc<dyn Hittable>>,
+    cdf: Vec<f64>, // 面积的前缀和, 已归一化
+    total_area: f64,
+    power: f64,
+}
+
+impl AreaSampler {
+    fn new(tris: Vec<Arc<dyn Hittable>>, areas: &[f64]) -> Self {
+        let mut cdf = Vec::with_capacity(areas.len());
+        let mut total_area = 0.0;
+        for area in areas {
+            total_area += area;
+            cdf.push(total_area);
+        }
+        // 空网格或面积全为0时不能作为光源采样, 功率记为0, 选择光源时不会选中
+        let power = if total_area > 0.0 {
+            for c in cdf.iter_mut() {
+                *c /= total_area;
+            }
+            tris.iter().map(|tri| tri.power()).sum()
+        } else {
+            0.0
+        };
+        Self {
+            tris,
+            cdf,
+            total_area,
+            power,
+        }
+    }
+
+    fn sample(&self) -> Option<&Arc<dyn Hittable>> {
+        if self.total_area <= 0.0 {
+            return None;
+        }
+        let k = rand::thread_rng().gen::<f64>();
+        let idx = self.cdf.partition_point(|&c| c < k);
+        Some(&self.tris[idx.min(self.tris.len() - 1)])
+    }
+}
+
+// 三角面和BVH放在Arc里, clone只增加引用计数, 多个实例共享同一份网格
+#[derive(Clone)]
 pub struct ObjModel {
-    triangles: BvhNode,
+    triangles: Arc<dyn Hittable>,
+    sampler: Arc<AreaSampler>,
     pub center: Point3,
 }
 
 impl ObjModel {
+    fn build(tris_list: HittableList, areas: &[f64]) -> Self {
+        let st_instant = Instant::now();
+        // 支持AVX时用8叉树一次测试8个孩子, 否则用4叉树和SSE
+        let triangles: Arc<dyn Hittable> = if WideBvh8::avx_available() {
+            Arc::new(WideBvh8::new_from_list(&tris_list, 0.0, 1.0))
+        } else {
+            Arc::new(WideBvh4::new_from_list(&tris_list, 0.0, 1.0))
+        };
+        println!(
+            "  Built BVH of {} triangles in {}",
+            style(tris_list.objects.len()).yellow(),
+            style(format!("{:.2?}", st_instant.elapsed())).yellow()
+        );
+
+        Self {
+            center: triangles
+                .bounding_box(0.0, 1.0)
+                .map_or(Point3::default(), |b| (b.maximum + b.minimum) / 2.0),
+            triangles,
+            sampler: Arc::new(AreaSampler::new(tris_list.objects, areas)),
+        }
+    }
+
     pub fn new_from_file<T>(file_obj: &str, scale: f64, mat: T) -> Self
     where
         T: Material + Send + Sync + Clone + 'static,
@@ -32,6 +106,7 @@ impl ObjModel {
         println!("🎰 Imortinging model...");
 
         let mut tris_list = HittableList::default();
+        let mut areas = Vec::new();
 
         match tobj::load_obj(
             file_obj,
@@ -54,7 +129,7 @@ impl ObjModel {
                         // let mat = Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
 
                         // 处理模型三角面
-                        tris_list.add(Arc::new(Triangle::new(
+                        let tri = Triangle::new(
                             [
                                 Point3::new(
                                     mesh.positions[3 * i] as f64,
@@ -73,7 +148,9 @@ impl ObjModel {
                                 ) * scale,
                             ],
                             mat.clone(),
-                        )));
+                        );
+                        areas.push(tri.area());
+                        tris_list.add(Arc::new(tri));
                     }
                 }
             }
@@ -84,17 +161,14 @@ impl ObjModel {
             ),
         }
 
-        let triangles = BvhNode::new_from_list(&mut tris_list, 0.0, 1.0);
-        Self {
-            center: (triangles.node_box.maximum + triangles.node_box.minimum) / 2.0,
-            triangles,
-        }
+        ObjModel::build(tris_list, &areas)
     }
 
     pub fn new_from_file_with_texture(file_obj: &str, scale: f64, file_texture: &str) -> Self {
         println!("🎰 Imortinging model...");
 
         let mut tris_list = HittableList::default();
+        let mut areas = Vec::new();
 
         let mut img = RgbImage::default();
         img = match image::open(file_texture) {
@@ -141,7 +215,7 @@ impl ObjModel {
                         let mat = Lambertian::new(text);
 
                         // 处理模型三角面
-                        tris_list.add(Arc::new(Triangle::new(
+                        let tri = Triangle::new(
                             [
                                 Point3::new(
                                     mesh.positions[3 * i] as f64,
@@ -160,7 +234,9 @@ impl ObjModel {
                                 ) * scale,
                             ],
                             mat,
-                        )));
+                        );
+                        areas.push(tri.area());
+                        tris_list.add(Arc::new(tri));
                     }
                 }
             }
@@ -171,17 +247,14 @@ impl ObjModel {
             ),
         }
 
-        let triangles = BvhNode::new_from_list(&mut tris_list, 0.0, 1.0);
-        Self {
-            center: (triangles.node_box.maximum + triangles.node_box.minimum) / 2.0,
-            triangles,
-        }
+        ObjModel::build(tris_list, &areas)
     }
 
     pub fn new_from_file_with_rand_texture(file_obj: &str, scale: f64, file_texture: &str) -> Self {
         println!("🎰 Imortinging model...");
 
         let mut tris_list = HittableList::default();
+        let mut areas = Vec::new();
 
         let mut rng = rand::thread_rng();
 
@@ -230,7 +303,7 @@ impl ObjModel {
                         let mat = Lambertian::new(text);
 
                         // 处理模型三角面
-                        tris_list.add(Arc::new(Triangle::new(
+                        let tri = Triangle::new(
                             [
                                 Point3::new(
                                     mesh.positions[3 * i] as f64,
@@ -249,7 +322,9 @@ impl ObjModel {
                                 ) * scale,
                             ],
                             mat,
-                        )));
+                        );
+                        areas.push(tri.area());
+                        tris_list.add(Arc::new(tri));
                     }
                 }
             }
@@ -260,11 +335,185 @@ impl ObjModel {
             ),
         }
 
-        let triangles = BvhNode::new_from_list(&mut tris_list, 0.0, 1.0);
-        Self {
-            center: (triangles.node_box.maximum + triangles.node_box.minimum) / 2.0,
-            triangles,
+        ObjModel::build(tris_list, &areas)
+    }
+
+    // 按.mtl中的材质创建Principled材质, 支持PBR扩展(Pr, Pm, Ps, Pc等)
+    pub fn new_from_file_with_mtl(file_obj: &str, scale: f64) -> Self {
+        println!("🎰 Imortinging model...");
+
+        let mut tris_list = HittableList::default();
+        let mut areas = Vec::new();
+        let dir = Path::new(file_obj)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        match tobj::load_obj(
+            file_obj,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+        ) {
+            Ok((models, mats)) => {
+                let mats: Vec<MtlMaterial> = match mats {
+                    Ok(mats) => mats.iter().map(|mat| MtlMaterial::new(mat, dir)).collect(),
+                    Err(_) => {
+                        println!(
+                            "  Warning: {}{}",
+                            style("Failed to load MTL file of ").yellow(),
+                            style(file_obj).yellow()
+                        );
+                        Vec::new()
+                    }
+                };
+                let default_mat = Principled::new(Color::new(0.5, 0.5, 0.5));
+
+                for model in models.iter() {
+                    let mesh = &model.mesh;
+                    let mtl = mesh.material_id.and_then(|id| mats.get(id));
+
+                    for idx in 0..mesh.indices.len() / 3 {
+                        let i = mesh.indices[idx * 3] as usize;
+                        let j = mesh.indices[idx * 3 + 1] as usize;
+                        let k = mesh.indices[idx * 3 + 2] as usize;
+
+                        // 处理材质, 有贴图时每个三角面带上自己的纹理坐标
+                        let mat = match mtl {
+                            Some(mtl) if !mesh.texcoords.is_empty() => {
+                                let uv = |i: usize| {
+                                    (
+                                        mesh.texcoords[2 * i] as f64,
+                                        mesh.texcoords[2 * i + 1] as f64,
+                                    )
+                                };
+                                mtl.with_uv([uv(i), uv(j), uv(k)])
+                            }
+                            Some(mtl) => mtl.base.clone(),
+                            None => default_mat.clone(),
+                        };
+
+                        // 处理模型三角面
+                        let tri = Triangle::new(
+                            [
+                                Point3::new(
+                                    mesh.positions[3 * i] as f64,
+                                    mesh.positions[3 * i + 1] as f64,
+                                    mesh.positions[3 * i + 2] as f64,
+                                ) * scale,
+                                Point3::new(
+                                    mesh.positions[3 * j] as f64,
+                                    mesh.positions[3 * j + 1] as f64,
+                                    mesh.positions[3 * j + 2] as f64,
+                                ) * scale,
+                                Point3::new(
+                                    mesh.positions[3 * k] as f64,
+                                    mesh.positions[3 * k + 1] as f64,
+                                    mesh.positions[3 * k + 2] as f64,
+                                ) * scale,
+                            ],
+                            mat,
+                        );
+                        areas.push(tri.area());
+                        tris_list.add(Arc::new(tri));
+                    }
+                }
+            }
+            Err(_) => println!(
+                "  Error: {}{}",
+                style("Failed to load OBJ file: ").red(),
+                style(file_obj).yellow()
+            ),
+        }
+
+        ObjModel::build(tris_list, &areas)
+    }
+}
+
+type UVMap = TriangleUV<ImageTexture>;
+// 把一张贴图绑定到材质的某个参数上
+type ApplyMap = fn(Principled, UVMap) -> Principled;
+
+// .mtl中的一个材质, 贴图需要在每个三角面上按纹理坐标重新绑定
+struct MtlMaterial {
+    base: Principled,
+    maps: Vec<(ApplyMap, Arc<ImageTexture>)>,
+}
+
+impl MtlMaterial {
+    fn new(mat: &tobj::Material, dir: &Path) -> Self {
+        let param = |key: &str| {
+            mat.unknown_param
+                .get(key)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+
+        let [r, g, b] = mat.diffuse;
+        let mut base = Principled::new(Color::new(r as f64, g as f64, b as f64));
+        if let Some(roughness) = param("Pr") {
+            base = base.with_roughness(roughness);
+        }
+        if let Some(metallic) = param("Pm") {
+            base = base.with_metallic(metallic);
+        }
+        if let Some(sheen) = param("Ps") {
+            base = base.with_sheen(sheen);
+        }
+        if let Some(clearcoat) = param("Pc") {
+            base = base.with_clearcoat(clearcoat);
+        }
+        if let Some(clearcoat_roughness) = param("Pcr") {
+            base = base.with_clearcoat_gloss(1.0 - clearcoat_roughness);
         }
+        if let Some(anisotropic) = param("aniso") {
+            base = base.with_anisotropic(anisotropic);
+        }
+        if mat.optical_density > 1.0 {
+            base = base.with_ior(mat.optical_density as f64);
+        }
+        if mat.dissolve < 1.0 {
+            base = base.with_transmission(1.0 - mat.dissolve as f64);
+        }
+
+        let mut maps: Vec<(ApplyMap, Arc<ImageTexture>)> = Vec::new();
+        let mut add_map = |file: Option<&String>, apply: ApplyMap| {
+            if let Some(file) = file.filter(|file| !file.is_empty()) {
+                let path = dir.join(file.trim());
+                let tex = ImageTexture::new_form_file(path.to_str().unwrap_or(file));
+                maps.push((apply, Arc::new(tex)));
+            }
+        };
+        add_map(
+            Some(&mat.diffuse_texture),
+            Principled::with_base_color_texture::<UVMap>,
+        );
+        add_map(
+            mat.unknown_param.get("map_Pr"),
+            Principled::with_roughness_texture::<UVMap>,
+        );
+        add_map(
+            mat.unknown_param.get("map_Pm"),
+            Principled::with_metallic_texture::<UVMap>,
+        );
+        add_map(
+            mat.unknown_param.get("map_Ps"),
+            Principled::with_sheen_texture::<UVMap>,
+        );
+        add_map(
+            mat.unknown_param.get("map_Pc"),
+            Principled::with_clearcoat_texture::<UVMap>,
+        );
+
+        Self { base, maps }
+    }
+
+    fn with_uv(&self, uv: [(f64, f64); 3]) -> Principled {
+        self.maps
+            .iter()
+            .fold(self.base.clone(), |mat, (apply, tex)| {
+                apply(mat, TriangleUV::new(uv, tex.clone()))
+            })
     }
 }
 
@@ -273,7 +522,47 @@ impl Hittable for ObjModel {
         self.triangles.bounding_box(st_time, ed_time)
     }
 
+    // 逐个变换三角面的顶点, 实例的包围盒不会因旋转而变大
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.sampler
+            .tris
+            .iter()
+            .filter_map(|tri| tri.transformed_box(m, st_time, ed_time))
+            .reduce(|a, b| AABB::surrounding_box(&a, &b))
+    }
+
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         self.triangles.hit(r, t_min, t_max)
     }
+
+    // 网格表面上的面积密度处处为 1/A, 方向v上每个交点都可能是采样点, 需把它们的立体角密度相加
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if self.sampler.total_area <= 0.0 {
+            return 0.0;
+        }
+        let r = Ray::new(*orig, v.unit_vector(), time);
+        let mut pdf = 0.0;
+        let mut t_min = 0.001;
+        while let Some(rec) = self.triangles.hit(&r, t_min, f64::INFINITY) {
+            let cosine = Vec3::dot(&r.dir, &rec.normal).abs();
+            pdf += rec.t.powi(2) / (cosine * self.sampler.total_area);
+            t_min = rec.t + 0.001;
+        }
+        pdf
+    }
+
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
+        match self.sampler.sample() {
+            Some(tri) => tri.random(orig, time),
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.sampler.power
+    }
+
+    fn can_sample_direction(&self) -> bool {
+        self.sampler.total_area > 0.0
+    }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::basic::mat4::Mat4;
 use crate::basic::onb::ONB;
 use crate::bvh::aabb::AABB;
 use crate::utility::*;
@@ -62,9 +63,10 @@ impl<T: Material + Sync + Send> Hittable for Sphere<T> {
             u,
             v,
             front_face: bool::default(),
+            medium: None,
         };
 
-        rec.set_face_normal(&r, outward_normal);
+        rec.set_face_normal(r, outward_normal);
 
         Some(rec)
     }
@@ -76,8 +78,18 @@ impl<T: Material + Sync + Send> Hittable for Sphere<T> {
         ))
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(*orig, *v, 0.0), 0.001, INFINITY) {
+    // 球变换后是椭球, 第i个坐标的半径为r乘以矩阵第i行的长度
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        let center = m.transform_point(self.center);
+        let mut half = Vec3::default();
+        for i in 0..3 {
+            half[i] = self.radius * Vec3::new(m.m[i][0], m.m[i][1], m.m[i][2]).length();
+        }
+        Some(AABB::new(center - half, center + half))
+    }
+
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(&Ray::new(*orig, *v, time), 0.001, f64::INFINITY) {
             let cos_theta_max =
                 (1.0 - self.radius.powi(2) / (self.center - *orig).length_squared()).sqrt();
             let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
@@ -87,11 +99,15 @@ impl<T: Material + Sync + Send> Hittable for Sphere<T> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         let direction = self.center - orig;
         let distance_squared = direction.length_squared();
         let uvw = ONB::build_from_w(direction);
 
         uvw.local(Vec3::rand_to_sphere(self.radius, distance_squared))
     }
+
+    fn power(&self) -> f64 {
+        4.0 * PI * self.radius.powi(2) * PI * self.mat.emitted_radiance()
+    }
 }
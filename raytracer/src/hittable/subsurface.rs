@@ -0,0 +1,243 @@
+use rand::Rng;
+
+use super::{HitRecord, Hittable};
+use crate::basic::onb::ONB;
+use crate::bvh::aabb::AABB;
+use crate::material::dielectric::Interior;
+use crate::material::microfacet::{DielectricInterface, TrowbridgeReitz};
+use crate::material::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::utility::*;
+
+// 随机游走最多的散射次数
+const MAX_STEPS: usize = 1024;
+
+// 次表面散射(皮肤、蜡、大理石、玉石等)
+// boundary必须是封闭的曲面, 它自身的材质被忽略; 表面是电介质界面,
+// 折射进入内部的光线在材质采样中完成随机游走, 再从最后一个散射点射向边界,
+// 到达边界时作为背面的交点继续着色, 粗糙表面在出射处也能做光源采样
+// 可以与电介质嵌套, 内部的吸收已经由随机游走计算, interior中不再设置吸收
+pub struct Subsurface<H: Hittable> {
+    boundary: H,
+    interior: Interior,
+    distribution: TrowbridgeReitz,
+    sigma_s: Color, // 散射系数
+    sigma_t: Color, // 消光系数, 散射与吸收系数之和
+}
+
+impl<H: Hittable> Subsurface<H> {
+    pub fn new(boundary: H, ir: f64, sigma_a: Color, sigma_s: Color) -> Self {
+        Self {
+            boundary,
+            interior: Interior::new(ir),
+            distribution: TrowbridgeReitz::new(0.0),
+            sigma_s,
+            sigma_t: sigma_a + sigma_s,
+        }
+    }
+
+    // 用平均自由程和单次散射反照率给出介质, 两者都按RGB分别给定
+    pub fn new_from_albedo(boundary: H, ir: f64, albedo: Color, mean_free_path: Color) -> Self {
+        let sigma_t = Color::new(
+            1.0 / mean_free_path.x,
+            1.0 / mean_free_path.y,
+            1.0 / mean_free_path.z,
+        );
+        Self {
+            boundary,
+            interior: Interior::new(ir),
+            distribution: TrowbridgeReitz::new(0.0),
+            sigma_s: albedo * sigma_t,
+            sigma_t,
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.distribution = TrowbridgeReitz::new(roughness);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.interior = self.interior.with_priority(priority);
+        self
+    }
+
+    // 不与其他电介质嵌套时, 入射一侧与透射一侧的折射率之比
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            1.0 / self.interior.ior
+        } else {
+            self.interior.ior
+        }
+    }
+
+    // 界面的eta是法线背面与正面之比
+    fn interface(&self, eta: f64) -> DielectricInterface {
+        DielectricInterface::new(1.0 / eta, self.distribution)
+    }
+
+    // 从边界上的p点沿dir进入介质随机游走, 直到射向边界的一段不再发生散射
+    // 返回这一段的起点, 方向和路径吞吐量
+    fn random_walk(
+        &self,
+        mut p: Point3,
+        mut dir: Vec3,
+        mut beta: Color,
+        time: f64,
+    ) -> Option<(Point3, Vec3, Color)> {
+        let mut rng = rand::thread_rng();
+        for step in 0..MAX_STEPS {
+            let ray = Ray::new(p, dir.unit_vector(), time);
+            // 边界不封闭时光线会漏出去, 当作被吸收
+            let hit = self.boundary.hit(&ray, 0.001, f64::INFINITY)?;
+            let (scatter, weight) = self.free_flight(&ray, hit.t, &beta);
+            beta = beta * weight;
+
+            match scatter {
+                Some(scatter) => {
+                    // 各向同性的相函数
+                    p = scatter;
+                    dir = Vec3::rand_unit_vector();
+                }
+                None => return Some((ray.orig, ray.dir, beta)),
+            }
+
+            if step >= 3 {
+                let q = (1.0 - beta.x.max(beta.y).max(beta.z)).max(0.0);
+                if rng.gen::<f64>() < q {
+                    return None;
+                }
+                beta /= 1.0 - q;
+            }
+        }
+        None
+    }
+
+    // 沿dir方向在介质中行进, 按当前吞吐量的比例选择一个通道采样自由程,
+    // 避免各通道的权重在长路径上相差越来越大
+    // 返回散射点(None表示先到达边界)和路径吞吐量的权重
+    fn free_flight(&self, ray: &Ray, t_max: f64, beta: &Color) -> (Option<Point3>, Color) {
+        let mut rng = rand::thread_rng();
+        let total = beta.x + beta.y + beta.z;
+        let prob = if total > 0.0 {
+            *beta / total
+        } else {
+            Color::new(1.0, 1.0, 1.0) / 3.0
+        };
+        let u = rng.gen::<f64>();
+        let channel = if u < prob.x {
+            self.sigma_t.x
+        } else if u < prob.x + prob.y {
+            self.sigma_t.y
+        } else {
+            self.sigma_t.z
+        };
+        let t = -(1.0 - rng.gen::<f64>()).ln() / channel;
+
+        let dist = t.min(t_max);
+        let tr = Color::new(
+            (-self.sigma_t.x * dist).exp(),
+            (-self.sigma_t.y * dist).exp(),
+            (-self.sigma_t.z * dist).exp(),
+        );
+        if t < t_max {
+            // 各通道采样概率密度按选择概率的加权平均
+            let density = prob * self.sigma_t * tr;
+            let pdf = density.x + density.y + density.z;
+            (Some(ray.at(t)), self.sigma_s * tr / pdf)
+        } else {
+            let density = prob * tr;
+            (None, tr / (density.x + density.y + density.z))
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Subsurface<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut rec = self.boundary.hit(r, t_min, t_max)?;
+        rec.mat_ptr = self;
+        Some(rec)
+    }
+
+    fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
+        self.boundary.bounding_box(st_time, ed_time)
+    }
+}
+
+impl<H: Hittable> Material for Subsurface<H> {
+    fn flags(&self) -> LobeFlags {
+        let lobe = if self.distribution.is_smooth() {
+            LobeFlags::SPECULAR
+        } else {
+            LobeFlags::GLOSSY | LobeFlags::SPECULAR
+        };
+        lobe | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(self.interior)
+    }
+
+    // 只包含留在物体外面的部分: 正面的反射和背面向外的透射
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let uvw = ONB::build_from_w(rec.normal);
+        let wi = uvw.to_local(wi.unit_vector());
+        if (wi.z > 0.0) != rec.front_face {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let value = self
+            .interface(eta)
+            .eval(&uvw.to_local(-r_in.dir.unit_vector()), &wi);
+        Color::new(value, value, value)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let uvw = ONB::build_from_w(rec.normal);
+        let sample = self
+            .interface(eta)
+            .sample(&uvw.to_local(-r_in.dir.unit_vector()))?;
+        let wi = uvw.local(sample.wi);
+        let weight = Color::new(sample.weight, sample.weight, sample.weight);
+
+        // 从外面打到表面时rec.normal朝外, 折射进入内部; 从里面打到时朝内, 反射留在内部
+        if (sample.wi.z > 0.0) == rec.front_face {
+            let lobe = if rec.front_face {
+                LobeFlags::REFLECTION
+            } else {
+                LobeFlags::TRANSMISSION
+            };
+            return Some(if sample.specular {
+                BSDFSample::new_specular(wi, weight, lobe)
+            } else {
+                BSDFSample::new(
+                    wi,
+                    weight * sample.pdf,
+                    sample.pdf,
+                    LobeFlags::GLOSSY | lobe,
+                )
+            });
+        }
+
+        // 进入内部的部分不能与光源采样结合, 当作镜面瓣
+        // 游走结束时光线仍在内部, 从正面进入算透射, 从背面反射回内部算反射
+        let lobe = if rec.front_face {
+            LobeFlags::TRANSMISSION
+        } else {
+            LobeFlags::REFLECTION
+        };
+        let (origin, dir, beta) = self.random_walk(rec.p, wi, weight, r_in.tm)?;
+        Some(BSDFSample::new_specular(dir, beta, lobe).with_origin(origin))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let uvw = ONB::build_from_w(rec.normal);
+        let wi = uvw.to_local(wi.unit_vector());
+        if (wi.z > 0.0) != rec.front_face {
+            return 0.0;
+        }
+        self.interface(eta)
+            .pdf(&uvw.to_local(-r_in.dir.unit_vector()), &wi)
+    }
+}
@@ -1,6 +1,7 @@
 use rand::Rng;
 
 use super::{HitRecord, Hittable};
+use crate::basic::mat4::Mat4;
 use crate::bvh::aabb::AABB;
 use crate::material::Material;
 use crate::utility::*;
@@ -50,13 +51,17 @@ impl<T: Material> Triangle<T> {
             area,
         }
     }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
 }
 
 impl<T: Material + Sync + Send> Hittable for Triangle<T> {
     fn bounding_box(&self, st_time: f64, ed_time: f64) -> Option<AABB> {
         let eps = 0.0001;
-        let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-        let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
         for v in self.ver.iter() {
             for i in 0..3 {
@@ -68,6 +73,21 @@ impl<T: Material + Sync + Send> Hittable for Triangle<T> {
         Some(AABB::new(min - eps, max + eps))
     }
 
+    fn transformed_box(&self, m: &Mat4, st_time: f64, ed_time: f64) -> Option<AABB> {
+        let eps = 0.0001;
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+
+        for v in self.ver.iter() {
+            let v = m.transform_point(*v);
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i])
+            }
+        }
+        Some(AABB::new(min - eps, max + eps))
+    }
+
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         // let origin = r.orig;
         let t = Vec3::dot(&r.dir, &self.normal);
@@ -94,6 +114,7 @@ impl<T: Material + Sync + Send> Hittable for Triangle<T> {
                     mat_ptr: &self.mat,
                     normal: Vec3::default(),
                     front_face: bool::default(),
+                    medium: None,
                 };
                 rec.set_face_normal(r, self.normal);
 
@@ -103,10 +124,10 @@ impl<T: Material + Sync + Send> Hittable for Triangle<T> {
         None
     }
 
-    fn pdf_value(&self, orig: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(*orig, *v, 0.0), 0.001, INFINITY) {
+    fn pdf_value(&self, orig: &Point3, v: &Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(&Ray::new(*orig, *v, time), 0.001, f64::INFINITY) {
             let distance_squared = rec.t.powi(2) * v.length_squared();
-            let cosine = Vec3::dot(&v, &rec.normal).abs() / v.length();
+            let cosine = Vec3::dot(v, &rec.normal).abs() / v.length();
 
             distance_squared / (cosine * self.area)
         } else {
@@ -114,7 +135,7 @@ impl<T: Material + Sync + Send> Hittable for Triangle<T> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, time: f64) -> Vec3 {
         let mut rng = rand::thread_rng();
         let mut k1 = rng.gen::<f64>();
         let mut k2 = rng.gen::<f64>();
@@ -123,6 +144,11 @@ impl<T: Material + Sync + Send> Hittable for Triangle<T> {
             k2 = 1. - k2;
         }
 
-        k1 * (self.ver[1] - self.ver[0]) + k1 * (self.ver[2] - self.ver[0]) - orig
+        // 在三角形上均匀取点
+        self.ver[0] + k1 * (self.ver[1] - self.ver[0]) + k2 * (self.ver[2] - self.ver[0]) - orig
+    }
+
+    fn power(&self) -> f64 {
+        self.area * PI * self.mat.emitted_radiance()
     }
 }
@@ -8,6 +8,7 @@ mod basic;
 mod bvh;
 mod hittable;
 mod material;
+mod medium;
 mod pdf;
 mod scene;
 mod texture;
@@ -23,6 +24,7 @@ use basic::camera::Camera;
 use scene::*;
 
 use std::char::MAX;
+use std::sync::Arc;
 use std::{fs::File, process::exit};
 
 use image::{ImageBuffer, RgbImage};
@@ -40,7 +42,6 @@ fn main() {
     const IMGAE_QUALITY: u8 = 100;
     const SAMPLE_PER_PIXEL: usize = 2000;
     const MAX_DEPTH: usize = 50;
-    const THREAD_NUM: usize = 8;
     let path = "output/output.jpg";
 
     // Clear screen
@@ -52,12 +53,12 @@ fn main() {
 
     // Generate image
     let thread_pool = multi_thread::gen_img_with_multi_threads(
-        THREAD_NUM,
+        multi_thread::THREAD_NUM,
         IMAGE_WIDTH,
         IMAGE_HEIGHT,
         SAMPLE_PER_PIXEL,
         MAX_DEPTH,
-        Color::new(0., 0., 0.),
+        Arc::new(Color::new(0., 0., 0.)),
         world,
         lights,
         cam,
@@ -0,0 +1,116 @@
+use super::microfacet::{fresnel_conductor, TrowbridgeReitz};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::basic::onb::ONB;
+use crate::hittable::HitRecord;
+use crate::pdf::{ggx_pdf::GgxPDF, PDF};
+use crate::utility::*;
+
+// GGX微表面导体, 复折射率 eta + i*k 决定金属的颜色
+#[derive(Copy, Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    // 以下常见金属的n, k分别取650nm, 550nm, 450nm附近的测量值
+    pub fn new_gold(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn new_copper(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn new_aluminium(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn new_silver(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        let wi = uvw.to_local(wi.unit_vector());
+        let wh = wo + wi;
+        if wo.z <= 0.0 || wi.z <= 0.0 || wh.near_zero() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let wh = wh.unit_vector();
+
+        // f * cos(wi) = D * G * F / (4 * cos(wo))
+        let fresnel = fresnel_conductor(Vec3::dot(&wi, &wh), &self.eta, &self.k);
+        fresnel * (self.distribution.d(&wh) * self.distribution.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let unit_direction = r_in.dir.unit_vector();
+        if self.distribution.is_smooth() {
+            let cos_theta = Vec3::dot(&-unit_direction, &rec.normal);
+            return Some(BSDFSample::new_specular(
+                Vec3::reflect(&unit_direction, &rec.normal),
+                fresnel_conductor(cos_theta, &self.eta, &self.k),
+                LobeFlags::REFLECTION,
+            ));
+        }
+
+        let wi = GgxPDF::new(rec.normal, -unit_direction, self.distribution).generate();
+        let pdf = self.pdf(r_in, rec, &wi, ctx);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample::new(
+            wi,
+            self.eval(r_in, rec, &wi, ctx),
+            pdf,
+            LobeFlags::GLOSSY | LobeFlags::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        GgxPDF::new(rec.normal, -r_in.dir, self.distribution).value(wi)
+    }
+
+    fn flags(&self) -> LobeFlags {
+        if self.distribution.is_smooth() {
+            LobeFlags::SPECULAR | LobeFlags::REFLECTION
+        } else {
+            LobeFlags::GLOSSY | LobeFlags::REFLECTION
+        }
+    }
+}
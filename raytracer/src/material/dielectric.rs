@@ -1,50 +1,243 @@
-use super::{Material, ScatterRecord};
+use super::microfacet::fresnel_dielectric;
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
 use crate::hittable::HitRecord;
 use crate::utility::*;
 
 use rand::Rng;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Copy, Clone)]
 pub struct Dielectric {
-    ir: f64, // Index of Refraction
+    interior: Interior, // 折射率, 吸收和嵌套的优先级
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let refraction_ratio = if (rec.front_face) {
-            1.0 / self.ir
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let eta = ctx.eta.unwrap_or(if (rec.front_face) {
+            1.0 / self.interior.ior
         } else {
-            self.ir
-        };
-
+            self.interior.ior
+        });
         let unit_direction = r_in.dir.unit_vector();
         let cos_theta = Vec3::dot(&-unit_direction, &rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
 
-        let cannot_refract = (refraction_ratio * sin_theta > 1.0);
-        let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::thread_rng().gen()
-        {
-            Vec3::reflect(&unit_direction, &rec.normal)
+        // 嵌套时两侧折射率可能很接近, 用精确的菲涅尔公式, eta为1时界面完全不可见
+        let cannot_refract = (eta * sin_theta > 1.0);
+        if cannot_refract || fresnel_dielectric(cos_theta, 1.0 / eta) > rand::thread_rng().gen() {
+            Some(BSDFSample::new_specular(
+                Vec3::reflect(&unit_direction, &rec.normal),
+                Color::new(1., 1., 1.),
+                LobeFlags::REFLECTION,
+            ))
         } else {
-            Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
-        };
+            Some(BSDFSample::new_specular(
+                Vec3::refract(&unit_direction, &rec.normal, eta),
+                Color::new(1., 1., 1.),
+                LobeFlags::TRANSMISSION,
+            ))
+        }
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::SPECULAR | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
+    }
 
-        Some(ScatterRecord::new_spec(
-            Ray::new(rec.p, direction, r_in.tm),
-            Color::new(1., 1., 1.),
-        ))
+    fn interior(&self) -> Option<Interior> {
+        Some(self.interior)
     }
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self {
+            interior: Interior::new(ir),
+        }
+    }
+
+    // 光在内部走过distance后剩下的颜色, 例如有色玻璃取厚度为1时透过的颜色
+    pub fn with_absorption(mut self, color: Color, distance: f64) -> Self {
+        self.interior = self.interior.with_absorption(color, distance);
+        self
+    }
+
+    // 几个电介质重叠时, 重叠部分属于优先级最高的那个, 例如杯中的水优先级高于杯子
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.interior = self.interior.with_priority(priority);
+        self
+    }
+}
+
+static NEXT_INTERIOR_ID: AtomicUsize = AtomicUsize::new(0);
+
+// 电介质的内部, id区分不同的物体
+#[derive(Copy, Clone)]
+pub struct Interior {
+    id: usize,
+    pub ior: f64,
+    pub absorption: Color, // 吸收系数, 按Beer-Lambert定律衰减
+    pub priority: i32,
+}
+
+impl Interior {
+    // 每次构造得到新的id; 网格的各个面复制的是同一份材质, 因此id相同
+    pub fn new(ior: f64) -> Self {
+        Self {
+            id: NEXT_INTERIOR_ID.fetch_add(1, Ordering::Relaxed),
+            ior,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+        }
+    }
+
+    pub fn with_absorption(mut self, color: Color, distance: f64) -> Self {
+        assert!(
+            distance > 0.0,
+            "Absorption distance should be positive, got {}.",
+            distance
+        );
+        let sigma_a = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance;
+        self.absorption = Color::new(sigma_a(color.x), sigma_a(color.y), sigma_a(color.z));
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+// 光线当前处在哪些电介质内部, 光线所在的介质是其中优先级最高的, 优先级相同时取最后进入的
+// 按材质构造时分配的id区分物体; 同一个材质值用在几个物体上时它们共用id, 需要区分的重叠物体应各自构造材质
+#[derive(Clone, Default)]
+pub struct InteriorStack {
+    entries: Vec<Interior>,
+}
+
+impl InteriorStack {
+    fn current(&self) -> Option<usize> {
+        let mut current: Option<usize> = None;
+        for (i, interior) in self.entries.iter().enumerate() {
+            match current {
+                Some(j) if self.entries[j].priority > interior.priority => {}
+                _ => current = Some(i),
+            }
+        }
+        current
+    }
+
+    fn ior(&self) -> f64 {
+        self.current().map_or(1.0, |i| self.entries[i].ior)
+    }
+
+    // 在当前的电介质中走过dist后的透射率
+    pub fn transmittance(&self, dist: f64) -> Color {
+        match self.current() {
+            Some(i) => {
+                let sigma_a = self.entries[i].absorption;
+                Color::new(
+                    (-sigma_a.x * dist).exp(),
+                    (-sigma_a.y * dist).exp(),
+                    (-sigma_a.z * dist).exp(),
+                )
+            }
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // 穿过内部为interior的电介质的表面, entering表示从外面进入
+    // 返回界面两侧折射率之比和透射后所在的电介质; 界面被优先级更高的电介质覆盖时比值为None, 光线应直接穿过
+    pub fn cross(&self, interior: Interior, entering: bool) -> (Option<f64>, Self) {
+        let mut next = self.clone();
+        if entering {
+            next.entries.push(interior);
+            return match self.current() {
+                Some(i) if self.entries[i].priority > interior.priority => (None, next),
+                _ => (Some(self.ior() / interior.ior), next),
+            };
+        }
+
+        // 不在栈中时(如相机在物体内部)按离开到当前所在的介质处理
+        let pos = match self.entries.iter().rposition(|e| e.id == interior.id) {
+            Some(pos) => pos,
+            None => return (Some(interior.ior / self.ior()), next),
+        };
+        next.entries.remove(pos);
+        if self.current() == Some(pos) {
+            (Some(interior.ior / next.ior()), next)
+        } else {
+            (None, next)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_eta(eta: Option<f64>, expected: f64) {
+        assert!((eta.unwrap() - expected).abs() < 1e-12, "{:?}", eta);
+    }
+
+    #[test]
+    fn higher_priority_interior_covers_nested_surface() {
+        let glass = Interior::new(1.5);
+        let water = Interior::new(1.33).with_priority(1);
+
+        // 先进入杯子, 再进入水, 界面两侧是玻璃和水
+        let (eta, in_glass) = InteriorStack::default().cross(glass, true);
+        assert_eta(eta, 1.0 / 1.5);
+        let (eta, in_water) = in_glass.cross(water, true);
+        assert_eta(eta, 1.5 / 1.33);
+
+        // 在水中碰到杯子的内壁, 被水覆盖, 直接穿过
+        let (eta, still_in_water) = in_water.cross(glass, false);
+        assert!(eta.is_none());
+        assert_eta(still_in_water.cross(water, false).0, 1.33);
+
+        // 反过来先进入水时杯子的表面被覆盖
+        let (_, in_water) = InteriorStack::default().cross(water, true);
+        let (eta, both) = in_water.cross(glass, true);
+        assert!(eta.is_none());
+        // 离开水回到杯子里
+        let (eta, in_glass) = both.cross(water, false);
+        assert_eta(eta, 1.33 / 1.5);
+        assert_eta(in_glass.cross(glass, false).0, 1.5);
+    }
+
+    #[test]
+    fn same_priority_prefers_last_entered() {
+        let a = Interior::new(1.5);
+        let b = Interior::new(1.2);
+        let (_, in_a) = InteriorStack::default().cross(a, true);
+        let (eta, in_both) = in_a.cross(b, true);
+        assert_eta(eta, 1.5 / 1.2);
+        // 离开先进入的a不改变所在的电介质
+        let (eta, in_b) = in_both.cross(a, false);
+        assert!(eta.is_none());
+        assert_eta(in_b.cross(b, false).0, 1.2);
+    }
+
+    #[test]
+    fn transmittance_uses_current_interior() {
+        let clear = Interior::new(1.5);
+        let tinted = Interior::new(1.5)
+            .with_absorption(Color::new(0.5, 0.25, 1.0), 2.0)
+            .with_priority(1);
+        let (_, stack) = InteriorStack::default().cross(clear, true);
+        assert!((stack.transmittance(3.0) - Color::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        let (_, stack) = stack.cross(tinted, true);
+        let tr = stack.transmittance(4.0);
+        assert!(
+            (tr - Color::new(0.25, 0.0625, 1.0)).length() < 1e-12,
+            "{:?}",
+            tr
+        );
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Use Schlick's approximation for reflectance
-        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    #[test]
+    #[should_panic]
+    fn absorption_rejects_non_positive_distance() {
+        Interior::new(1.5).with_absorption(Color::new(0.5, 0.5, 0.5), 0.0);
     }
 }
@@ -1,34 +1,99 @@
 use super::Material;
+use crate::basic::blackbody::blackbody;
+use crate::basic::ies::IesProfile;
 use crate::hittable::HitRecord;
 use crate::texture::solid_color::SolidColor;
 use crate::texture::Texture;
 use crate::utility::*;
 
+use std::sync::Arc;
+
 #[derive(Clone)]
 pub struct DiffuseLight<T: Texture> {
     emit: T,
+    intensity: f64,
+    two_sided: bool,
+    profile: Option<(Arc<IesProfile>, Vec3)>, // 配光曲线和水平角0度的方向, 按出射方向调制光强
+    profile_scale: f64,                       // 配光曲线下的功率与均匀发光之比
+}
+
+// 配光曲线给出的是光强, 平面发光体的辐亮度为 光强 / 出射方向的余弦
+// 比余弦衰减得慢的掠射方向平面发光体无法达到, 辐亮度限制在最大光强对应的值以内
+fn profile_radiance(value: f64, cos: f64) -> f64 {
+    if cos <= 0.0 {
+        0.0
+    } else {
+        (value / cos).min(1.0)
+    }
 }
 
 impl<T: Texture> DiffuseLight<T> {
     pub fn new(emit: T) -> Self {
-        Self { emit }
+        Self {
+            emit,
+            intensity: 1.0,
+            two_sided: false,
+            profile: None,
+            profile_scale: 1.0,
+        }
+    }
+
+    // 亮度与颜色分开设置, 发出的辐亮度为 颜色 * intensity
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // 正反两面都发光
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    // 以表面法线为灯具主轴, up在表面上的投影为水平角0度, 此时intensity对应最大光强
+    pub fn with_ies_profile(mut self, profile: IesProfile, up: Vec3) -> Self {
+        self.profile_scale =
+            profile.hemisphere_integral(|value, cos| profile_radiance(value, cos) * cos) / PI;
+        self.profile = Some((Arc::new(profile), up));
+        self
     }
 }
 
 impl DiffuseLight<SolidColor> {
     pub fn new_form_color(color: Color) -> Self {
-        Self {
-            emit: SolidColor::new(color),
-        }
+        DiffuseLight::new(SolidColor::new(color))
+    }
+
+    // 色温(K)对应的黑体颜色, 亮度为1, 用with_intensity调节强度
+    pub fn new_from_temperature(kelvin: f64) -> Self {
+        DiffuseLight::new(SolidColor::new(blackbody(kelvin)))
     }
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
     fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
-        if rec.front_face {
-            self.emit.value(u, v, p)
+        if !rec.front_face && !self.two_sided {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let mut emitted = self.emit.value(u, v, p) * self.intensity;
+        if let Some((profile, up)) = &self.profile {
+            // rec.normal总是朝着入射光线的一侧
+            let dir = -r_in.dir.unit_vector();
+            let value = profile.value_in_dir(&rec.normal, up, &dir);
+            emitted *= profile_radiance(value, Vec3::dot(&rec.normal, &dir));
+        }
+        emitted
+    }
+
+    fn emitted_radiance(&self) -> f64 {
+        let radiance = self.emit.value(0.5, 0.5, Point3::default()).luminance()
+            * self.intensity
+            * self.profile_scale;
+        if self.two_sided {
+            2.0 * radiance
         } else {
-            Color::new(0.0, 0.0, 0.0)
+            radiance
         }
     }
 }
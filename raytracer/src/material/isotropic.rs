@@ -1,6 +1,5 @@
-use super::{Material, ScatterRecord};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
 use crate::hittable::HitRecord;
-use crate::pdf::cosine_pdf::CosinePDF;
 use crate::texture::{solid_color::SolidColor, Texture};
 use crate::utility::*;
 
@@ -22,21 +21,26 @@ impl Isotropic<SolidColor> {
     }
 }
 
+// 各向同性的相函数, 不乘余弦项
 impl<T: Texture> Material for Isotropic<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        Some(ScatterRecord::new_spec(
-            Ray::new(rec.p, Vec3::rand_in_unit_sphere(), r_in.tm),
-            self.albedo.value(rec.u, rec.v, rec.p),
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.p) / (4.0 * PI)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        Some(BSDFSample::new(
+            Vec3::rand_unit_vector(),
+            self.albedo.value(rec.u, rec.v, rec.p) / (4.0 * PI),
+            1.0 / (4.0 * PI),
+            LobeFlags::DIFFUSE | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION,
         ))
     }
 
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
-        // TODO
-        let cosine = Vec3::dot(&rec.normal, &scattered.dir.unit_vector());
-        if cosine.is_sign_negative() {
-            0.0
-        } else {
-            cosine / PI
-        }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::DIFFUSE | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
     }
 }
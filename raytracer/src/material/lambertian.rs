@@ -1,7 +1,6 @@
-use super::{Material, ScatterRecord};
-use crate::basic::onb::ONB;
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
 use crate::hittable::HitRecord;
-use crate::pdf::cosine_pdf::CosinePDF;
+use crate::pdf::{cosine_pdf::CosinePDF, PDF};
 use crate::texture::{solid_color::SolidColor, Texture};
 use crate::utility::*;
 
@@ -11,20 +10,35 @@ pub struct Lambertian<T: Texture> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        Some(ScatterRecord::new_diff(
-            CosinePDF::new(rec.normal),
-            self.albedo.value(rec.u, rec.v, rec.p),
-        ))
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let cosine = Vec3::dot(&rec.normal, &wi.unit_vector());
+        if cosine <= 0.0 {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            self.albedo.value(rec.u, rec.v, rec.p) * cosine / PI
+        }
     }
 
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
-        let cosine = Vec3::dot(&rec.normal, &scattered.dir.unit_vector());
-        if cosine.is_sign_negative() {
-            0.0
-        } else {
-            cosine / PI
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let wi = CosinePDF::new(rec.normal).generate();
+        let pdf = self.pdf(r_in, rec, &wi, ctx);
+        if pdf <= 0.0 {
+            return None;
         }
+        Some(BSDFSample::new(
+            wi,
+            self.eval(r_in, rec, &wi, ctx),
+            pdf,
+            LobeFlags::DIFFUSE | LobeFlags::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        CosinePDF::new(rec.normal).value(wi)
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::DIFFUSE | LobeFlags::REFLECTION
     }
 }
 
@@ -0,0 +1,367 @@
+use super::microfacet::{fresnel_dielectric, refract, DielectricInterface, TrowbridgeReitz};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::basic::onb::ONB;
+use crate::hittable::HitRecord;
+use crate::utility::*;
+
+use rand::Rng;
+
+// 涂层和底层之间最多反弹的次数
+const MAX_DEPTH: usize = 10;
+
+// 在任意材质上加一层透明涂层(清漆、车漆等)
+// 涂层上表面是电介质界面, 下表面直接接触底层材质, 中间的介质只吸收不散射
+// 光在两层之间的多次反弹用随机游走估计, eval和pdf都是随机的无偏估计
+#[derive(Copy, Clone)]
+pub struct Layered<M: Material> {
+    base: M,
+    ir: f64,
+    distribution: TrowbridgeReitz,
+    thickness: f64,
+    tint: Color, // 沿法线穿过单位厚度涂层后的颜色
+}
+
+// 底层材质的采样结果, 方向在局部坐标系中
+struct BaseSample {
+    wi: Vec3,
+    weight: Color, // f / pdf
+    pdf: f64,
+    specular: bool,
+}
+
+// 把局部坐标系中的方向交给底层材质, 底层在涂层下面, 不使用外面电介质的折射率
+struct Frame<'a> {
+    uvw: ONB,
+    r_in: &'a Ray,
+    rec: &'a HitRecord<'a>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(r_in: &'a Ray, rec: &'a HitRecord<'a>) -> Self {
+        Self {
+            uvw: ONB::build_from_w(rec.normal),
+            r_in,
+            rec,
+        }
+    }
+
+    // 以wo为出射方向的入射光线
+    fn ray(&self, wo: &Vec3) -> Ray {
+        Ray::new(self.rec.p, self.uvw.local(-*wo), self.r_in.tm)
+    }
+
+    fn eval<M: Material>(&self, base: &M, wo: &Vec3, wi: &Vec3) -> Color {
+        base.eval(
+            &self.ray(wo),
+            self.rec,
+            &self.uvw.local(*wi),
+            &ShadingContext::default(),
+        )
+    }
+
+    fn pdf<M: Material>(&self, base: &M, wo: &Vec3, wi: &Vec3) -> f64 {
+        base.pdf(
+            &self.ray(wo),
+            self.rec,
+            &self.uvw.local(*wi),
+            &ShadingContext::default(),
+        )
+    }
+
+    fn sample<M: Material>(&self, base: &M, wo: &Vec3) -> Option<BaseSample> {
+        let sample = base.sample(&self.ray(wo), self.rec, &ShadingContext::default())?;
+        if sample.pdf <= 0.0 || sample.wi.near_zero() {
+            return None;
+        }
+        Some(BaseSample {
+            wi: self.uvw.to_local(sample.wi.unit_vector()),
+            weight: sample.f / sample.pdf,
+            pdf: sample.pdf,
+            specular: sample.flags.is_specular(),
+        })
+    }
+}
+
+fn power_heuristic(f: f64, g: f64) -> f64 {
+    if f <= 0.0 {
+        return 0.0;
+    }
+    f * f / (f * f + g * g)
+}
+
+impl<M: Material> Layered<M> {
+    pub fn new(base: M, ir: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            ir,
+            distribution: TrowbridgeReitz::new(roughness),
+            thickness: 1.0,
+            tint: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_thickness(mut self, thickness: f64) -> Self {
+        self.thickness = thickness.max(0.0);
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn interface(&self) -> DielectricInterface {
+        DielectricInterface::new(self.ir, self.distribution)
+    }
+
+    // 沿w方向穿过涂层的透射率
+    fn transmittance(&self, w: &Vec3) -> Color {
+        let d = self.thickness / w.z.abs().max(1e-4);
+        Color::new(
+            self.tint.x.powf(d),
+            self.tint.y.powf(d),
+            self.tint.z.powf(d),
+        )
+    }
+
+    // 从外部wo方向进入涂层, 返回涂层内向下的方向和权重
+    // 光滑涂层的折射是确定的, 只乘透射率
+    fn enter(&self, coat: &DielectricInterface, wo: &Vec3) -> Option<(Vec3, f64)> {
+        if coat.is_smooth() {
+            let w = refract(wo, &Vec3::new(0.0, 0.0, 1.0), self.ir)?;
+            let fresnel = fresnel_dielectric(wo.z, self.ir);
+            return Some((w, (1.0 - fresnel) / (self.ir * self.ir)));
+        }
+        let sample = coat.sample(wo).filter(|s| s.wi.z < 0.0)?;
+        Some((sample.wi, sample.weight))
+    }
+
+    // 光从外部wi方向进入涂层, 返回涂层内向下的方向, 该方向穿出涂层到wi的权重及其概率密度
+    fn connect(&self, coat: &DielectricInterface, wi: &Vec3) -> Option<(Vec3, f64, f64)> {
+        if coat.is_smooth() {
+            let d = refract(wi, &Vec3::new(0.0, 0.0, 1.0), self.ir)?;
+            let fresnel = fresnel_dielectric(wi.z, self.ir);
+            // 内外立体角之比抵消了辐亮度的缩放
+            return Some((d, (1.0 - fresnel) * wi.z / d.z.abs(), 1.0));
+        }
+        let sample = coat.sample(wi).filter(|s| s.wi.z < 0.0)?;
+        let value = coat.eval(&sample.wi, wi);
+        Some((sample.wi, value / sample.pdf, sample.pdf))
+    }
+
+    fn eval_local(&self, frame: &Frame, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let coat = self.interface();
+        let smooth = coat.is_smooth();
+
+        // 涂层表面的直接反射
+        let r = coat.eval(wo, wi);
+        let mut f = Color::new(r, r, r);
+
+        let (mut w, weight) = match self.enter(&coat, wo) {
+            Some(entered) => entered,
+            None => return f,
+        };
+        let mut beta = Color::new(weight, weight, weight);
+        let exit = self.connect(&coat, wi);
+        let mut rng = rand::thread_rng();
+
+        for depth in 0..MAX_DEPTH {
+            // 到达底层
+            beta = beta * self.transmittance(&w);
+            let wo_base = -w;
+
+            // 底层直接连接到穿出涂层的方向
+            if let Some((d, value, pdf)) = exit {
+                let u = -d;
+                let fb = frame.eval(&self.base, &wo_base, &u);
+                if !fb.near_zero() {
+                    let wt = if smooth {
+                        1.0
+                    } else {
+                        power_heuristic(pdf, frame.pdf(&self.base, &wo_base, &u))
+                    };
+                    f += beta * fb * self.transmittance(&u) * (value * wt);
+                }
+            }
+
+            // 底层采样新的方向, 粗糙涂层时可能直接穿出到wi
+            let bs = match frame.sample(&self.base, &wo_base) {
+                Some(bs) if bs.wi.z > 0.0 => bs,
+                _ => break,
+            };
+            beta = beta * bs.weight;
+            w = bs.wi;
+            if !smooth {
+                let value = coat.eval(&-w, wi);
+                if value > 0.0 {
+                    let wt = if bs.specular {
+                        1.0
+                    } else {
+                        power_heuristic(bs.pdf, coat.pdf(wi, &-w))
+                    };
+                    f += beta * self.transmittance(&w) * (value * wt);
+                }
+            }
+            beta = beta * self.transmittance(&w);
+
+            // 在涂层上表面的内侧反射回底层
+            if smooth {
+                beta *= fresnel_dielectric(-w.z, self.ir);
+                w = Vec3::new(w.x, w.y, -w.z);
+            } else {
+                match coat.sample(&-w) {
+                    Some(s) if s.wi.z < 0.0 => {
+                        beta *= s.weight;
+                        w = s.wi;
+                    }
+                    _ => break,
+                }
+            }
+
+            if depth >= 3 {
+                let q = (1.0 - beta.x.max(beta.y).max(beta.z)).max(0.0);
+                if rng.gen::<f64>() < q {
+                    break;
+                }
+                beta /= 1.0 - q;
+            }
+        }
+        f
+    }
+
+    // 用与eval相同的路径结构近似概率密度, 混入少量均匀分布保证不为0
+    fn pdf_local(&self, frame: &Frame, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let coat = self.interface();
+        let mut pdf = coat.pdf(wo, wi);
+
+        if let (Some((w, _)), Some((d, _, exit_pdf))) =
+            (self.enter(&coat, wo), self.connect(&coat, wi))
+        {
+            let (wo_base, u) = (-w, -d);
+            if coat.is_smooth() {
+                // 换算到涂层外的立体角
+                pdf += frame.pdf(&self.base, &wo_base, &u) * wi.z / (self.ir * self.ir * u.z);
+            } else {
+                let base_pdf = frame.pdf(&self.base, &wo_base, &u);
+                pdf += power_heuristic(exit_pdf, base_pdf) * base_pdf;
+                if let Some(bs) = frame
+                    .sample(&self.base, &wo_base)
+                    .filter(|bs| bs.wi.z > 0.0)
+                {
+                    let coat_pdf = coat.pdf(&-bs.wi, wi);
+                    pdf += if bs.specular {
+                        coat_pdf
+                    } else {
+                        power_heuristic(coat_pdf, bs.pdf) * coat_pdf
+                    };
+                }
+            }
+        }
+        0.1 / (4.0 * PI) + 0.9 * pdf
+    }
+
+    // 在两层之间随机游走直到穿出涂层, 返回出射方向, f / pdf以及路径是否全为镜面
+    fn sample_local(&self, frame: &Frame, wo: &Vec3) -> Option<(Vec3, Color, bool)> {
+        let coat = self.interface();
+        let s = coat.sample(wo)?;
+        let mut beta = Color::new(s.weight, s.weight, s.weight);
+        if s.wi.z > 0.0 {
+            return Some((s.wi, beta, s.specular));
+        }
+        let mut w = s.wi;
+        let mut specular = s.specular;
+        let mut rng = rand::thread_rng();
+
+        for depth in 0..MAX_DEPTH {
+            beta = beta * self.transmittance(&w);
+            let bs = frame.sample(&self.base, &-w).filter(|bs| bs.wi.z > 0.0)?;
+            beta = beta * bs.weight;
+            specular &= bs.specular;
+            w = bs.wi;
+
+            beta = beta * self.transmittance(&w);
+            let s = coat.sample(&-w)?;
+            beta *= s.weight;
+            specular &= s.specular;
+            if s.wi.z > 0.0 {
+                return Some((s.wi, beta, specular));
+            }
+            w = s.wi;
+
+            if depth >= 3 {
+                let q = (1.0 - beta.x.max(beta.y).max(beta.z)).max(0.0);
+                if rng.gen::<f64>() < q {
+                    return None;
+                }
+                beta /= 1.0 - q;
+            }
+        }
+        None
+    }
+}
+
+impl<M: Material> Material for Layered<M> {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        if wi.near_zero() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let frame = Frame::new(r_in, rec);
+        let wo = frame.uvw.to_local(-r_in.dir.unit_vector());
+        let wi = frame.uvw.to_local(wi.unit_vector());
+        self.eval_local(&frame, &wo, &wi)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let frame = Frame::new(r_in, rec);
+        let wo = frame.uvw.to_local(-r_in.dir.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let (wi, weight, specular) = self.sample_local(&frame, &wo)?;
+        if specular {
+            return Some(BSDFSample::new_specular(
+                frame.uvw.local(wi),
+                weight,
+                LobeFlags::REFLECTION,
+            ));
+        }
+        // 游走得到的是f / pdf, 另外估计pdf用于和光源采样结合
+        let pdf = self.pdf_local(&frame, &wo, &wi);
+        Some(BSDFSample::new(
+            frame.uvw.local(wi),
+            weight * pdf,
+            pdf,
+            LobeFlags::GLOSSY | LobeFlags::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        if wi.near_zero() {
+            return 0.0;
+        }
+        let frame = Frame::new(r_in, rec);
+        let wo = frame.uvw.to_local(-r_in.dir.unit_vector());
+        let wi = frame.uvw.to_local(wi.unit_vector());
+        self.pdf_local(&frame, &wo, &wi)
+    }
+
+    fn flags(&self) -> LobeFlags {
+        let coat = if self.distribution.is_smooth() {
+            LobeFlags::SPECULAR
+        } else {
+            LobeFlags::GLOSSY
+        };
+        let base = if self.base.flags().is_non_specular() {
+            LobeFlags::GLOSSY
+        } else {
+            LobeFlags::NONE
+        };
+        coat | base | LobeFlags::REFLECTION
+    }
+}
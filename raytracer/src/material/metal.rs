@@ -1,7 +1,9 @@
-use super::{Material, ScatterRecord};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
 use crate::hittable::HitRecord;
 use crate::utility::*;
 
+// 在镜面反射方向上加球内均匀的随机扰动来模拟粗糙度, 扰动后指向表面以下的光线被吸收
+// 需要基于微表面模型的粗糙金属时用Conductor
 #[derive(Copy, Clone)]
 pub struct Metal {
     albedo: Color,
@@ -9,27 +11,61 @@ pub struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    // 采样时权重恒为albedo, 所以BSDF与余弦的乘积是albedo * pdf
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        self.albedo * self.pdf(r_in, rec, wi, ctx)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
         let reflected = Vec3::reflect(&r_in.dir.unit_vector(), &rec.normal);
+        if self.fuzz <= 0.0 {
+            return Some(BSDFSample::new_specular(
+                reflected,
+                self.albedo,
+                LobeFlags::REFLECTION,
+            ));
+        }
 
-        Some(ScatterRecord::new_spec(
-            Ray::new(
-                rec.p,
-                reflected + self.fuzz * Vec3::rand_in_unit_sphere(),
-                r_in.tm,
-            ),
-            self.albedo,
+        let wi = reflected + self.fuzz * Vec3::rand_in_unit_sphere();
+        if Vec3::dot(&wi, &rec.normal) <= 0.0 {
+            return None;
+        }
+        let pdf = self.pdf(r_in, rec, &wi, ctx);
+        Some(BSDFSample::new(
+            wi,
+            self.albedo * pdf,
+            pdf,
+            LobeFlags::GLOSSY | LobeFlags::REFLECTION,
         ))
-        // let scattered = Ray::new(
-        //     rec.p,
-        //     reflected + self.fuzz * Vec3::rand_in_unit_sphere(),
-        //     r_in.tm,
-        // );
-        // if Vec3::dot(&scattered.dir, &rec.normal) > 0.0 {
-        //     Some(ScatterRecord::new_spec(scattered, self.albedo))
-        // } else {
-        //     None
-        // }
+    }
+
+    // 沿wi的射线穿过以反射方向为球心, fuzz为半径的球, 球内均匀分布的点落在这条射线上的概率密度
+    // 对射线上球内的一段积分 t^2 dt, 再除以球的体积
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        let wi = wi.unit_vector();
+        if self.fuzz <= 0.0 || Vec3::dot(&wi, &rec.normal) <= 0.0 {
+            return 0.0;
+        }
+        let reflected = Vec3::reflect(&r_in.dir.unit_vector(), &rec.normal);
+        let b = Vec3::dot(&wi, &reflected);
+        let disc = b * b - 1.0 + self.fuzz * self.fuzz;
+        if disc <= 0.0 {
+            return 0.0;
+        }
+        let t1 = (b - disc.sqrt()).max(0.0);
+        let t2 = b + disc.sqrt();
+        if t2 <= 0.0 {
+            return 0.0;
+        }
+        (t2.powi(3) - t1.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
+
+    fn flags(&self) -> LobeFlags {
+        if self.fuzz <= 0.0 {
+            LobeFlags::SPECULAR | LobeFlags::REFLECTION
+        } else {
+            LobeFlags::GLOSSY | LobeFlags::REFLECTION
+        }
     }
 }
 
@@ -41,3 +77,66 @@ impl Metal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::onb::ONB;
+
+    // pdf在上半球的积分应等于采样没有被吸收的比例
+    // pdf只在以反射方向为轴, 半角为asin(fuzz)的圆锥内不为0, 在圆锥上按网格积分
+    #[test]
+    fn pdf_integrates_to_accepted_fraction() {
+        let n = 200000;
+        let grid = 400;
+        for &fuzz in [0.3, 1.0].iter() {
+            let metal = Metal::new(Color::new(1.0, 1.0, 1.0), fuzz);
+            let r_in = Ray::new(Point3::new(-4.0, 1.0, 0.0), Vec3::new(4.0, -1.0, 0.0), 0.0);
+            let rec = HitRecord {
+                p: Point3::default(),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                mat_ptr: &metal,
+                t: 1.0,
+                u: 0.0,
+                v: 0.0,
+                front_face: true,
+                medium: None,
+            };
+            let ctx = ShadingContext::default();
+
+            let accepted = (0..n)
+                .filter(|_| metal.sample(&r_in, &rec, &ctx).is_some())
+                .count() as f64
+                / n as f64;
+            let uvw = ONB::build_from_w(Vec3::reflect(&r_in.dir.unit_vector(), &rec.normal));
+            let cos_max = (1.0 - fuzz * fuzz).sqrt();
+            let cell = (1.0 - cos_max) / grid as f64 * (2.0 * PI / grid as f64);
+            let mut integral = 0.0;
+            for i in 0..grid {
+                let cos_theta = cos_max + (1.0 - cos_max) * (i as f64 + 0.5) / grid as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                for j in 0..grid {
+                    let phi = 2.0 * PI * (j as f64 + 0.5) / grid as f64;
+                    let wi = uvw.local(Vec3::new(
+                        sin_theta * phi.cos(),
+                        sin_theta * phi.sin(),
+                        cos_theta,
+                    ));
+                    integral += metal.pdf(&r_in, &rec, &wi, &ctx) * cell;
+                }
+            }
+            assert!(accepted < 1.0);
+            assert!(
+                (integral - accepted).abs() < 0.01,
+                "{} != {}",
+                integral,
+                accepted
+            );
+
+            // 采样的方向可能被吸收, 取第一个留下的
+            let s = (0..).find_map(|_| metal.sample(&r_in, &rec, &ctx)).unwrap();
+            assert!((s.pdf - metal.pdf(&r_in, &rec, &s.wi, &ctx)).abs() < 1e-12);
+            assert!((s.f - metal.eval(&r_in, &rec, &s.wi, &ctx)).length() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::utility::*;
+
+use rand::Rng;
+
+// GGX(Trowbridge-Reitz)微表面分布, 所有方向都在以宏观法线为z轴的局部坐标系中
+// 各向异性时alpha_x, alpha_y分别对应局部坐标系的x轴和y轴
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    // 用感知上更均匀的roughness, alpha = roughness^2
+    pub fn new(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let alpha = (roughness * roughness).max(1e-4);
+        Self {
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+
+    // anisotropic取 [0, 1], 沿x轴方向的高光被拉长
+    pub fn new_anisotropic(roughness: f64, anisotropic: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let aspect = (1.0 - 0.9 * anisotropic.clamp(0.0, 1.0)).sqrt();
+        Self {
+            alpha_x: (roughness * roughness / aspect).max(1e-3),
+            alpha_y: (roughness * roughness * aspect).max(1e-3),
+        }
+    }
+
+    // 足够光滑时直接当作镜面处理, 否则分布过于尖锐, 数值上不稳定
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    // 法线分布函数D(h)
+    pub fn d(&self, wh: &Vec3) -> f64 {
+        if wh.z <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = wh.z * wh.z;
+        let e = (wh.x * wh.x / (self.alpha_x * self.alpha_x)
+            + wh.y * wh.y / (self.alpha_y * self.alpha_y))
+            / cos2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2 * cos2 * (1.0 + e) * (1.0 + e))
+    }
+
+    // Smith遮蔽函数中的辅助函数
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        // alpha^2 * tan^2, alpha取w方向上的粗糙度
+        let alpha2_tan2 = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / cos2;
+        0.5 * (-1.0 + (1.0 + alpha2_tan2).sqrt())
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // 高度相关的Smith遮蔽-阴影函数
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // 可见法线分布(Heitz 2018)采样微表面法线, 要求wo.z > 0
+    pub fn sample_wh(&self, wo: &Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let (u1, u2) = (rng.gen::<f64>(), rng.gen::<f64>());
+
+        // 拉伸到alpha = 1的半球上
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&vh, &t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).unit_vector()
+    }
+
+    // sample_wh得到wh的概率密度
+    pub fn pdf_wh(&self, wo: &Vec3, wh: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, wh).max(0.0) * self.d(wh) / wo.z
+    }
+}
+
+// 导体的菲涅尔反射率, eta + i*k为复折射率, 对RGB分别计算
+pub fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let c = cos_i.clamp(0.0, 1.0);
+    let f = |eta: f64, k: f64| {
+        let cos2 = c * c;
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * c * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Color::new(f(eta.x, k.x), f(eta.y, k.y), f(eta.z, k.z))
+}
+
+// 电介质的精确菲涅尔反射率, eta为透射侧与入射侧折射率之比
+// cos_i < 0 表示从透射侧入射
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // 全反射
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+// wo绕法线n折射, wo与n在同一侧, eta为透射侧与入射侧折射率之比
+// 发生全反射时返回None
+pub fn refract(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + (cos_i / eta - cos_t) * *n)
+}
+
+// 透射时的广义半程向量, 朝向wo一侧(z > 0)
+pub fn half_vector_transmit(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+    let wh = *wo + *wi * eta;
+    if wh.near_zero() {
+        return None;
+    }
+    let wh = wh.unit_vector();
+    Some(if wh.z < 0.0 { -wh } else { wh })
+}
+
+// 局部坐标系中以z轴为法线的电介质界面, 同时包含反射和折射
+// wo可以在界面任意一侧, 透射部分按辐亮度随折射率平方缩放
+#[derive(Copy, Clone)]
+pub struct DielectricInterface {
+    eta: f64, // -z一侧与+z一侧的折射率之比
+    distribution: TrowbridgeReitz,
+}
+
+// 界面采样结果, weight = f * |cos(wi)| / pdf
+// 镜面时pdf为选中该分支的概率
+pub struct InterfaceSample {
+    pub wi: Vec3,
+    pub weight: f64,
+    pub pdf: f64,
+    pub specular: bool,
+}
+
+impl DielectricInterface {
+    pub fn new(eta: f64, distribution: TrowbridgeReitz) -> Self {
+        Self { eta, distribution }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    // wo在-z一侧时把方向翻到+z一侧, 折射率之比取倒数
+    fn orient(&self, wo: &Vec3) -> (f64, f64) {
+        if wo.z < 0.0 {
+            (-1.0, 1.0 / self.eta)
+        } else {
+            (1.0, self.eta)
+        }
+    }
+
+    // BSDF与|cos(wi)|的乘积, 光滑界面返回0
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let (sign, eta) = self.orient(wo);
+        if self.is_smooth() {
+            return 0.0;
+        }
+        self.eval_oriented(&(sign * *wo), &(sign * *wi), eta)
+    }
+
+    // sample得到wi的概率密度, 光滑界面返回0
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let (sign, eta) = self.orient(wo);
+        if self.is_smooth() {
+            return 0.0;
+        }
+        self.pdf_oriented(&(sign * *wo), &(sign * *wi), eta)
+    }
+
+    // 先按可见法线采样微表面, 再按菲涅尔项在反射和折射之间随机选择
+    // 反射到界面另一侧或折射回同一侧时采样失败
+    pub fn sample(&self, wo: &Vec3) -> Option<InterfaceSample> {
+        let (sign, eta) = self.orient(wo);
+        let wo = sign * *wo;
+        if wo.z == 0.0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+
+        if self.is_smooth() {
+            let fresnel = fresnel_dielectric(wo.z, eta);
+            let (wi, weight, pdf) = if rng.gen::<f64>() < fresnel {
+                (Vec3::new(-wo.x, -wo.y, wo.z), 1.0, fresnel)
+            } else {
+                let wi = refract(&wo, &Vec3::new(0.0, 0.0, 1.0), eta)?;
+                (wi, 1.0 / (eta * eta), 1.0 - fresnel)
+            };
+            return Some(InterfaceSample {
+                wi: sign * wi,
+                weight,
+                pdf,
+                specular: true,
+            });
+        }
+
+        let wh = self.distribution.sample_wh(&wo);
+        let wi = if rng.gen::<f64>() < fresnel_dielectric(Vec3::dot(&wo, &wh), eta) {
+            Some(Vec3::reflect(&-wo, &wh)).filter(|wi| wi.z > 0.0)
+        } else {
+            refract(&wo, &wh, eta).filter(|wi| wi.z < 0.0)
+        }?;
+        let pdf = self.pdf_oriented(&wo, &wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(InterfaceSample {
+            wi: sign * wi,
+            weight: self.eval_oriented(&wo, &wi, eta) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn eval_oriented(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+
+        if wi.z > 0.0 {
+            let wh = *wo + *wi;
+            if wh.near_zero() {
+                return 0.0;
+            }
+            let wh = wh.unit_vector();
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, &wh), eta);
+            self.distribution.d(&wh) * self.distribution.g(wo, wi) * fresnel / (4.0 * wo.z)
+        } else {
+            let wh = match half_vector_transmit(wo, wi, eta) {
+                Some(wh) => wh,
+                None => return 0.0,
+            };
+            let (cos_o, cos_i) = (Vec3::dot(wo, &wh), Vec3::dot(wi, &wh));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return 0.0;
+            }
+            let fresnel = fresnel_dielectric(cos_o, eta);
+            let denom = (cos_i + cos_o / eta).powi(2);
+            // 辐亮度穿过界面时按折射率之比的平方缩放
+            self.distribution.d(&wh)
+                * self.distribution.g(wo, wi)
+                * (1.0 - fresnel)
+                * (cos_i * cos_o).abs()
+                / (wo.z * denom * eta * eta)
+        }
+    }
+
+    fn pdf_oriented(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+
+        if wi.z > 0.0 {
+            let wh = *wo + *wi;
+            if wh.near_zero() {
+                return 0.0;
+            }
+            let wh = wh.unit_vector();
+            let fresnel = fresnel_dielectric(Vec3::dot(wo, &wh), eta);
+            fresnel * self.distribution.pdf_wh(wo, &wh) / (4.0 * Vec3::dot(wo, &wh).abs())
+        } else {
+            let wh = match half_vector_transmit(wo, wi, eta) {
+                Some(wh) => wh,
+                None => return 0.0,
+            };
+            let (cos_o, cos_i) = (Vec3::dot(wo, &wh), Vec3::dot(wi, &wh));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return 0.0;
+            }
+            let fresnel = fresnel_dielectric(cos_o, eta);
+            // 半程向量到入射方向的雅可比
+            let denom = (cos_i + cos_o / eta).powi(2);
+            (1.0 - fresnel) * self.distribution.pdf_wh(wo, &wh) * cos_i.abs() / denom
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use super::dielectric::Interior;
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::hittable::HitRecord;
+use crate::texture::{solid_color::SolidColor, Texture};
+use crate::utility::*;
+
+use rand::Rng;
+
+// 按遮罩纹理混合两种材质, 遮罩取纹理的第一个通道, 为0时完全是a, 为1时完全是b
+// eval和pdf按权重混合; 采样时按权重选择一种材质, 保留它的f / pdf,
+// 这样其中的材质只能随机估计eval和pdf时(如分层材质)也保持无偏
+#[derive(Copy, Clone)]
+pub struct Mix<A: Material, B: Material, T: Texture> {
+    a: A,
+    b: B,
+    mask: T,
+}
+
+impl<A: Material, B: Material, T: Texture> Mix<A, B, T> {
+    pub fn new(a: A, b: B, mask: T) -> Self {
+        Self { a, b, mask }
+    }
+
+    fn amount(&self, rec: &HitRecord) -> f64 {
+        self.mask.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0)
+    }
+}
+
+impl<A: Material, B: Material> Mix<A, B, SolidColor> {
+    pub fn new_form_value(a: A, b: B, amount: f64) -> Self {
+        Self {
+            a,
+            b,
+            mask: SolidColor::new(Color::new(amount, amount, amount)),
+        }
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for Mix<A, B, T> {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let t = self.amount(rec);
+        let mut f = Color::new(0.0, 0.0, 0.0);
+        if t < 1.0 {
+            f += (1.0 - t) * self.a.eval(r_in, rec, wi, ctx);
+        }
+        if t > 0.0 {
+            f += t * self.b.eval(r_in, rec, wi, ctx);
+        }
+        f
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let t = self.amount(rec);
+        let sample = if rand::thread_rng().gen::<f64>() < t {
+            self.b.sample(r_in, rec, ctx)?
+        } else {
+            self.a.sample(r_in, rec, ctx)?
+        };
+        if sample.flags.is_specular() || sample.pdf <= 0.0 {
+            return Some(sample);
+        }
+        // 选中的概率与混合权重相同, 两者抵消
+        let pdf = self.pdf(r_in, rec, &sample.wi, ctx);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample::new(
+            sample.wi,
+            sample.f * (pdf / sample.pdf),
+            pdf,
+            sample.flags,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        let t = self.amount(rec);
+        let mut pdf = 0.0;
+        if t < 1.0 {
+            pdf += (1.0 - t) * self.a.pdf(r_in, rec, wi, ctx);
+        }
+        if t > 0.0 {
+            pdf += t * self.b.pdf(r_in, rec, wi, ctx);
+        }
+        pdf
+    }
+
+    fn flags(&self) -> LobeFlags {
+        self.a.flags() | self.b.flags()
+    }
+
+    // 混合的两种材质都能透射时共用同一个内部, 取a的
+    fn interior(&self) -> Option<Interior> {
+        self.a.interior().or_else(|| self.b.interior())
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        let t = self.amount(rec);
+        (1.0 - t) * self.a.emitted(r_in, rec, u, v, p) + t * self.b.emitted(r_in, rec, u, v, p)
+    }
+
+    fn emitted_radiance(&self) -> f64 {
+        let t = self
+            .mask
+            .value(0.5, 0.5, Point3::default())
+            .x
+            .clamp(0.0, 1.0);
+        (1.0 - t) * self.a.emitted_radiance() + t * self.b.emitted_radiance()
+    }
+}
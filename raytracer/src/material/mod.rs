@@ -1,50 +1,145 @@
+pub mod conductor;
 pub mod dielectric;
 pub mod diffuse_light;
 pub mod isotropic;
 pub mod lambertian;
+pub mod layered;
 pub mod metal;
+pub mod microfacet;
+pub mod mix;
+pub mod null;
+pub mod oren_nayar;
+pub mod phase;
+pub mod principled;
+pub mod rough_dielectric;
+pub mod volumetric;
 
 use crate::hittable::HitRecord;
-use crate::pdf::cosine_pdf::CosinePDF;
 use crate::utility::*;
+use dielectric::Interior;
 use lambertian::Lambertian;
 
-pub enum DiffOrSpec {
-    Specular(Ray),
-    Diffuse(CosinePDF),
+use std::ops::BitOr;
+
+// 散射方向所属的瓣, 可以用 | 组合
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LobeFlags(u8);
+
+impl LobeFlags {
+    pub const NONE: LobeFlags = LobeFlags(0);
+    pub const REFLECTION: LobeFlags = LobeFlags(1);
+    pub const TRANSMISSION: LobeFlags = LobeFlags(1 << 1);
+    pub const DIFFUSE: LobeFlags = LobeFlags(1 << 2);
+    pub const GLOSSY: LobeFlags = LobeFlags(1 << 3);
+    pub const SPECULAR: LobeFlags = LobeFlags(1 << 4);
+
+    pub fn contains(self, other: LobeFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // 镜面瓣是delta分布, 不能与光源采样结合
+    pub fn is_specular(self) -> bool {
+        self.contains(LobeFlags::SPECULAR)
+    }
+
+    // 含有漫反射或光泽瓣时才值得做光源采样
+    pub fn is_non_specular(self) -> bool {
+        self.0 & (LobeFlags::DIFFUSE.0 | LobeFlags::GLOSSY.0) != 0
+    }
 }
 
-pub struct ScatterRecord {
-    pub ray_type: DiffOrSpec,
-    pub attenuation: Color,
+impl BitOr for LobeFlags {
+    type Output = LobeFlags;
+
+    fn bitor(self, rhs: LobeFlags) -> LobeFlags {
+        LobeFlags(self.0 | rhs.0)
+    }
+}
+
+// 材质采样得到的散射方向
+// f已经乘上|cos(wi)|, 镜面瓣的f为衰减系数, pdf取1
+// 次表面散射的光线从另一点离开表面, 由origin给出, 否则从交点出发
+pub struct BSDFSample {
+    pub wi: Vec3,
+    pub f: Color,
+    pub pdf: f64,
+    pub flags: LobeFlags,
+    pub origin: Option<Point3>,
 }
 
-impl ScatterRecord {
-    pub fn new_diff(pdf: CosinePDF, attenuation: Color) -> Self {
+impl BSDFSample {
+    pub fn new(wi: Vec3, f: Color, pdf: f64, flags: LobeFlags) -> Self {
         Self {
-            ray_type: DiffOrSpec::Diffuse(pdf),
-            attenuation,
+            wi,
+            f,
+            pdf,
+            flags,
+            origin: None,
         }
     }
 
-    pub fn new_spec(ray: Ray, attenuation: Color) -> Self {
+    pub fn new_specular(wi: Vec3, attenuation: Color, flags: LobeFlags) -> Self {
         Self {
-            ray_type: DiffOrSpec::Specular(ray),
-            attenuation,
+            wi,
+            f: attenuation,
+            pdf: 1.0,
+            flags: flags | LobeFlags::SPECULAR,
+            origin: None,
         }
     }
+
+    pub fn with_origin(mut self, origin: Point3) -> Self {
+        self.origin = Some(origin);
+        self
+    }
 }
 
+// 积分器在交点处额外知道的信息
+// eta为界面入射一侧与透射一侧的折射率之比, 由光线所在的电介质决定; None时材质按自身的折射率对真空计算
+#[derive(Copy, Clone, Default)]
+pub struct ShadingContext {
+    pub eta: Option<f64>,
+}
+
+// wi均为世界坐标系中指向散射方向的向量, 不要求是单位向量
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    // BSDF与|cos(wi)|的乘积, 镜面瓣不计入
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // 采样散射方向, 返回None表示光线被吸收
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
         None
     }
 
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+    // sample得到wi的概率密度(立体角), 镜面瓣不计入
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
         0.0
     }
 
+    // 材质包含的所有瓣, 积分器据此决定是否做光源采样
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::NONE
+    }
+
+    // 不可见的表面, 只用来划分介质, 积分器让光线直接穿过而不计入反弹次数
+    fn is_null(&self) -> bool {
+        false
+    }
+
+    // 可以嵌套的电介质的内部, 积分器据此判断光线在哪个物体里面
+    // 透射时必须真正穿过这个表面, 一次采样就从别处出来的材质(如Subsurface)不应提供
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
+
     fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+
+    // 表面的平均辐亮度, 只用于按功率选择光源, 不必精确
+    fn emitted_radiance(&self) -> f64 {
+        0.0
+    }
 }
@@ -0,0 +1,25 @@
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::hittable::HitRecord;
+use crate::utility::*;
+
+// 介质的不可见边界, 光线原样穿过
+#[derive(Copy, Clone, Default)]
+pub struct Null;
+
+impl Material for Null {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        Some(BSDFSample::new_specular(
+            r_in.dir,
+            Color::new(1.0, 1.0, 1.0),
+            LobeFlags::TRANSMISSION,
+        ))
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::SPECULAR | LobeFlags::TRANSMISSION
+    }
+
+    fn is_null(&self) -> bool {
+        true
+    }
+}
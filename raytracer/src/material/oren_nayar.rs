@@ -0,0 +1,86 @@
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::basic::onb::ONB;
+use crate::hittable::HitRecord;
+use crate::pdf::{cosine_pdf::CosinePDF, PDF};
+use crate::texture::{solid_color::SolidColor, Texture};
+use crate::utility::*;
+
+// Oren-Nayar粗糙漫反射的定性模型, 表面微平面坡度的标准差sigma越大, 掠射光下越不像Lambertian
+// sigma由纹理的第一个通道给出, 单位为弧度
+#[derive(Copy, Clone)]
+pub struct OrenNayar<T: Texture, U: Texture> {
+    albedo: T,
+    sigma: U,
+}
+
+impl<T: Texture, U: Texture> OrenNayar<T, U> {
+    pub fn new(albedo: T, sigma: U) -> Self {
+        Self { albedo, sigma }
+    }
+}
+
+impl OrenNayar<SolidColor, SolidColor> {
+    // sigma以角度给出
+    pub fn new_form_color(albedo: Color, sigma: f64) -> Self {
+        let sigma = sigma.to_radians();
+        Self {
+            albedo: SolidColor::new(albedo),
+            sigma: SolidColor::new(Color::new(sigma, sigma, sigma)),
+        }
+    }
+}
+
+impl<T: Texture, U: Texture> Material for OrenNayar<T, U> {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        let wi = uvw.to_local(wi.unit_vector());
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let sigma2 = self.sigma.value(rec.u, rec.v, rec.p).x.powi(2);
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        // cos(phi_i - phi_o)
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        // sin(max(theta_i, theta_o)) * tan(min(theta_i, theta_o))
+        let sin_alpha_tan_beta = if wi.z > wo.z {
+            sin_o * sin_i / wi.z
+        } else {
+            sin_i * sin_o / wo.z
+        };
+
+        self.albedo.value(rec.u, rec.v, rec.p)
+            * ((a + b * cos_phi * sin_alpha_tan_beta) * wi.z / PI)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let wi = CosinePDF::new(rec.normal).generate();
+        let pdf = self.pdf(r_in, rec, &wi, ctx);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample::new(
+            wi,
+            self.eval(r_in, rec, &wi, ctx),
+            pdf,
+            LobeFlags::DIFFUSE | LobeFlags::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        CosinePDF::new(rec.normal).value(wi)
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::DIFFUSE | LobeFlags::REFLECTION
+    }
+}
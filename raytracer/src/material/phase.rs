@@ -0,0 +1,120 @@
+use crate::basic::onb::ONB;
+use crate::utility::*;
+
+use rand::Rng;
+
+// 参与介质的相函数, 只与散射角有关
+// cos_theta为光线原来的传播方向与散射后方向的夹角余弦, 向前散射时为1
+pub trait PhaseFunction: Send + Sync {
+    // 在单位球面上积分为1
+    fn p(&self, cos_theta: f64) -> f64;
+
+    // 按p采样散射角的余弦
+    fn sample_cos_theta(&self) -> f64;
+
+    // 按p采样散射方向, dir为传播方向
+    fn sample(&self, dir: &Vec3) -> Vec3 {
+        let cos_theta = self.sample_cos_theta().clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand::thread_rng().gen::<f64>();
+        ONB::build_from_w(*dir).local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct IsotropicPhase;
+
+impl PhaseFunction for IsotropicPhase {
+    fn p(&self, cos_theta: f64) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        1.0 - 2.0 * rand::thread_rng().gen::<f64>()
+    }
+}
+
+// g取 (-1, 1), 大于0时向前散射(雾, 云), 小于0时向后散射
+#[derive(Copy, Clone)]
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        Self {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        let g = self.g;
+        let u = rand::thread_rng().gen::<f64>();
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * u;
+        }
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    }
+}
+
+// 两个HG按权重混合, 同时有明显的前向峰和后向散射(如云, 生物组织)
+#[derive(Copy, Clone)]
+pub struct DoubleHenyeyGreenstein {
+    forward: HenyeyGreenstein,
+    backward: HenyeyGreenstein,
+    weight: f64, // forward所占的比例
+}
+
+impl DoubleHenyeyGreenstein {
+    pub fn new(g_forward: f64, g_backward: f64, weight: f64) -> Self {
+        Self {
+            forward: HenyeyGreenstein::new(g_forward),
+            backward: HenyeyGreenstein::new(g_backward),
+            weight: weight.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        self.weight * self.forward.p(cos_theta) + (1.0 - self.weight) * self.backward.p(cos_theta)
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        if rand::thread_rng().gen::<f64>() < self.weight {
+            self.forward.sample_cos_theta()
+        } else {
+            self.backward.sample_cos_theta()
+        }
+    }
+}
+
+// 远小于波长的粒子(空气分子)的散射, 前后对称
+#[derive(Copy, Clone, Default)]
+pub struct Rayleigh;
+
+impl PhaseFunction for Rayleigh {
+    fn p(&self, cos_theta: f64) -> f64 {
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    // 分布函数为 (mu^3 + 3mu + 4) / 8, 用卡尔达诺公式解三次方程
+    fn sample_cos_theta(&self) -> f64 {
+        let z = 4.0 * rand::thread_rng().gen::<f64>() - 2.0;
+        let s = (z * z + 1.0).sqrt();
+        (z + s).cbrt() + (z - s).cbrt()
+    }
+}
@@ -0,0 +1,522 @@
+use super::dielectric::Interior;
+use super::microfacet::{fresnel_dielectric, half_vector_transmit, refract, TrowbridgeReitz};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::basic::onb::ONB;
+use crate::hittable::HitRecord;
+use crate::texture::{solid_color::SolidColor, Texture};
+use crate::utility::*;
+
+use rand::Rng;
+use std::sync::Arc;
+
+type TextureRef = Arc<dyn Texture + Send + Sync>;
+
+// Disney principled BSDF(Burley 2012, 2015), 由漫反射+sheen, 高光, 清漆和透射四个瓣组成
+// 所有参数都可以用纹理给出, 标量参数取纹理的第一个通道
+// 各向异性的切线方向取法线构造的局部坐标系的u轴
+#[derive(Clone)]
+pub struct Principled {
+    base_color: TextureRef,
+    metallic: TextureRef,
+    roughness: TextureRef,
+    specular: TextureRef,
+    specular_tint: TextureRef,
+    anisotropic: TextureRef,
+    sheen: TextureRef,
+    sheen_tint: TextureRef,
+    clearcoat: TextureRef,
+    clearcoat_gloss: TextureRef,
+    transmission: TextureRef,
+    interior: Interior,
+    transmissive: bool, // 有透射时才作为可以嵌套的电介质
+}
+
+// 击中点处的参数值
+struct Params {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    anisotropic: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    transmission: f64,
+}
+
+fn constant(value: f64) -> TextureRef {
+    Arc::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Principled::new_from_texture(SolidColor::new(base_color))
+    }
+
+    pub fn new_from_texture<T: Texture + Send + Sync + 'static>(base_color: T) -> Self {
+        Self {
+            base_color: Arc::new(base_color),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            anisotropic: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            interior: Interior::new(1.5),
+            transmissive: false,
+        }
+    }
+
+    pub fn with_base_color_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.base_color = Arc::new(tex);
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f64) -> Self {
+        self.metallic = constant(metallic);
+        self
+    }
+
+    pub fn with_metallic_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.metallic = Arc::new(tex);
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = constant(roughness);
+        self
+    }
+
+    pub fn with_roughness_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.roughness = Arc::new(tex);
+        self
+    }
+
+    // 非金属的镜面反射强度, 0.5对应折射率1.5
+    pub fn with_specular(mut self, specular: f64) -> Self {
+        self.specular = constant(specular);
+        self
+    }
+
+    pub fn with_specular_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.specular = Arc::new(tex);
+        self
+    }
+
+    // 非金属高光向基础颜色偏移的程度
+    pub fn with_specular_tint(mut self, specular_tint: f64) -> Self {
+        self.specular_tint = constant(specular_tint);
+        self
+    }
+
+    pub fn with_specular_tint_texture<T: Texture + Send + Sync + 'static>(
+        mut self,
+        tex: T,
+    ) -> Self {
+        self.specular_tint = Arc::new(tex);
+        self
+    }
+
+    pub fn with_anisotropic(mut self, anisotropic: f64) -> Self {
+        self.anisotropic = constant(anisotropic);
+        self
+    }
+
+    pub fn with_anisotropic_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.anisotropic = Arc::new(tex);
+        self
+    }
+
+    // 掠射角处的额外反射, 用于布料
+    pub fn with_sheen(mut self, sheen: f64) -> Self {
+        self.sheen = constant(sheen);
+        self
+    }
+
+    pub fn with_sheen_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.sheen = Arc::new(tex);
+        self
+    }
+
+    pub fn with_sheen_tint(mut self, sheen_tint: f64) -> Self {
+        self.sheen_tint = constant(sheen_tint);
+        self
+    }
+
+    pub fn with_sheen_tint_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.sheen_tint = Arc::new(tex);
+        self
+    }
+
+    // 第二层固定折射率1.5的高光
+    pub fn with_clearcoat(mut self, clearcoat: f64) -> Self {
+        self.clearcoat = constant(clearcoat);
+        self
+    }
+
+    pub fn with_clearcoat_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.clearcoat = Arc::new(tex);
+        self
+    }
+
+    pub fn with_clearcoat_gloss(mut self, clearcoat_gloss: f64) -> Self {
+        self.clearcoat_gloss = constant(clearcoat_gloss);
+        self
+    }
+
+    pub fn with_clearcoat_gloss_texture<T: Texture + Send + Sync + 'static>(
+        mut self,
+        tex: T,
+    ) -> Self {
+        self.clearcoat_gloss = Arc::new(tex);
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: f64) -> Self {
+        self.transmission = constant(transmission);
+        self.transmissive = transmission > 0.0;
+        self
+    }
+
+    pub fn with_transmission_texture<T: Texture + Send + Sync + 'static>(mut self, tex: T) -> Self {
+        self.transmission = Arc::new(tex);
+        self.transmissive = true;
+        self
+    }
+
+    pub fn with_ior(mut self, ior: f64) -> Self {
+        self.interior.ior = ior;
+        self
+    }
+
+    // 透射部分与Dielectric相同, 可以设置内部的吸收和嵌套的优先级
+    pub fn with_absorption(mut self, color: Color, distance: f64) -> Self {
+        self.interior = self.interior.with_absorption(color, distance);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.interior = self.interior.with_priority(priority);
+        self
+    }
+
+    // 不与其他电介质嵌套时, 入射一侧与透射一侧的折射率之比
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            1.0 / self.interior.ior
+        } else {
+            self.interior.ior
+        }
+    }
+
+    fn params(&self, rec: &HitRecord) -> Params {
+        let scalar = |tex: &TextureRef| tex.value(rec.u, rec.v, rec.p).x.clamp(0.0, 1.0);
+        Params {
+            base_color: self.base_color.value(rec.u, rec.v, rec.p),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            anisotropic: scalar(&self.anisotropic),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_gloss: scalar(&self.clearcoat_gloss),
+            transmission: scalar(&self.transmission),
+        }
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+// 清漆层使用的GTR1分布
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_h * cos_h))
+}
+
+fn sample_gtr1(alpha: f64) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let a2 = alpha * alpha;
+    let cos2 = ((1.0 - a2.powf(1.0 - rng.gen::<f64>())) / (1.0 - a2)).clamp(0.0, 1.0);
+    let sin = (1.0 - cos2).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f64>();
+    Vec3::new(sin * phi.cos(), sin * phi.sin(), cos2.sqrt())
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const TRANSMISSION: usize = 3;
+
+// 击中点处展开的各个瓣, 方向都在局部坐标系中
+struct Lobes {
+    uvw: ONB,
+    wo: Vec3,
+    eta: f64, // 法线背面与正面的折射率之比
+    params: Params,
+    distribution: TrowbridgeReitz,
+    clearcoat_alpha: f64,
+    spec0: Color,          // 正入射时的高光颜色
+    dielectric_ratio: f64, // 透射部分所占比例, 这部分的反射用精确的菲涅尔项
+    prob: [f64; 4],        // 选择各个瓣的概率
+}
+
+impl Lobes {
+    // eta为入射一侧与透射一侧的折射率之比
+    fn new(mat: &Principled, r_in: &Ray, rec: &HitRecord, eta: f64) -> Self {
+        let params = mat.params(rec);
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        let eta = 1.0 / eta; // 换成法线背面与正面之比
+
+        let luminance = params.base_color.luminance();
+        let tint = if luminance > 0.0 {
+            params.base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+        let spec0 = lerp(
+            0.08 * params.specular * lerp(Color::new(1.0, 1.0, 1.0), tint, params.specular_tint),
+            params.base_color,
+            params.metallic,
+        );
+        let dielectric_ratio = (1.0 - params.metallic) * params.transmission;
+
+        let cos_o = wo.z.max(0.0);
+        let fresnel_o = fresnel_dielectric(cos_o, eta);
+        let schlick_o = schlick_weight(cos_o);
+        let mut prob = [0.0; 4];
+        // 只要某个瓣可能有贡献, 选择它的概率就不能为0
+        prob[DIFFUSE] = (1.0 - params.metallic)
+            * (1.0 - params.transmission)
+            * (luminance + 0.25 * params.sheen);
+        prob[SPECULAR] = (1.0 - dielectric_ratio)
+            * (spec0 + (Color::new(1.0, 1.0, 1.0) - spec0) * schlick_o).luminance()
+            + dielectric_ratio * fresnel_o;
+        prob[CLEARCOAT] = 0.25 * params.clearcoat * (0.04 + 0.96 * schlick_o);
+        // 宏观上全反射时粗糙的微表面仍可能折射
+        prob[TRANSMISSION] = dielectric_ratio * (1.0 - fresnel_o).max(0.1) * luminance;
+        let total: f64 = prob.iter().sum();
+        if total > 0.0 {
+            for p in prob.iter_mut() {
+                *p /= total;
+            }
+        }
+
+        Self {
+            uvw,
+            wo,
+            eta,
+            distribution: TrowbridgeReitz::new_anisotropic(params.roughness, params.anisotropic),
+            clearcoat_alpha: 0.1 * (1.0 - params.clearcoat_gloss) + 0.001 * params.clearcoat_gloss,
+            params,
+            spec0,
+            dielectric_ratio,
+            prob,
+        }
+    }
+
+    // f * |cos(wi)|
+    fn eval(&self, wi: &Vec3) -> Color {
+        let wo = self.wo;
+        let p = &self.params;
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        if wi.z > 0.0 {
+            let wh = wo + *wi;
+            if wh.near_zero() {
+                return Color::new(0.0, 0.0, 0.0);
+            }
+            let wh = wh.unit_vector();
+            let cos_d = Vec3::dot(wi, &wh);
+
+            // Burley漫反射与sheen
+            let fd90 = 0.5 + 2.0 * p.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let luminance = p.base_color.luminance();
+            let tint = if luminance > 0.0 {
+                p.base_color / luminance
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            let sheen = p.sheen
+                * lerp(Color::new(1.0, 1.0, 1.0), tint, p.sheen_tint)
+                * schlick_weight(cos_d);
+            let diffuse =
+                (p.base_color * (fd / PI) + sheen) * ((1.0 - p.metallic) * (1.0 - p.transmission));
+
+            // 高光
+            let schlick =
+                self.spec0 + (Color::new(1.0, 1.0, 1.0) - self.spec0) * schlick_weight(cos_d);
+            let fresnel = schlick * (1.0 - self.dielectric_ratio)
+                + Color::new(1.0, 1.0, 1.0)
+                    * (self.dielectric_ratio * fresnel_dielectric(Vec3::dot(&wo, &wh), self.eta));
+            let specular = fresnel
+                * (self.distribution.d(&wh) * self.distribution.g(&wo, wi) / (4.0 * wo.z * wi.z));
+
+            // 清漆
+            let clearcoat = 0.25
+                * p.clearcoat
+                * gtr1(wh.z, self.clearcoat_alpha)
+                * (0.04 + 0.96 * schlick_weight(cos_d))
+                * TrowbridgeReitz::new(0.5).g(&wo, wi)
+                / (4.0 * wo.z * wi.z);
+
+            (diffuse + specular + Color::new(clearcoat, clearcoat, clearcoat)) * wi.z
+        } else {
+            if self.dielectric_ratio <= 0.0 {
+                return Color::new(0.0, 0.0, 0.0);
+            }
+            let wh = match half_vector_transmit(&wo, wi, self.eta) {
+                Some(wh) => wh,
+                None => return Color::new(0.0, 0.0, 0.0),
+            };
+            let (cos_o, cos_i) = (Vec3::dot(&wo, &wh), Vec3::dot(wi, &wh));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return Color::new(0.0, 0.0, 0.0);
+            }
+            let fresnel = fresnel_dielectric(cos_o, self.eta);
+            let denom = (cos_i + cos_o / self.eta).powi(2);
+            // 辐亮度穿过界面时按折射率之比的平方缩放
+            p.base_color
+                * (self.dielectric_ratio
+                    * self.distribution.d(&wh)
+                    * self.distribution.g(&wo, wi)
+                    * (1.0 - fresnel)
+                    * (cos_i * cos_o).abs()
+                    / (wo.z * denom * self.eta * self.eta))
+        }
+    }
+
+    fn pdf(&self, wi: &Vec3) -> f64 {
+        let wo = self.wo;
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+
+        if wi.z > 0.0 {
+            let wh = wo + *wi;
+            if wh.near_zero() {
+                return 0.0;
+            }
+            let wh = wh.unit_vector();
+            let cos_oh = Vec3::dot(&wo, &wh).abs();
+            self.prob[DIFFUSE] * wi.z / PI
+                + self.prob[SPECULAR] * self.distribution.pdf_wh(&wo, &wh) / (4.0 * cos_oh)
+                + self.prob[CLEARCOAT] * gtr1(wh.z, self.clearcoat_alpha) * wh.z / (4.0 * cos_oh)
+        } else {
+            let wh = match half_vector_transmit(&wo, wi, self.eta) {
+                Some(wh) => wh,
+                None => return 0.0,
+            };
+            let (cos_o, cos_i) = (Vec3::dot(&wo, &wh), Vec3::dot(wi, &wh));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return 0.0;
+            }
+            let denom = (cos_i + cos_o / self.eta).powi(2);
+            self.prob[TRANSMISSION] * self.distribution.pdf_wh(&wo, &wh) * cos_i.abs() / denom
+        }
+    }
+
+    // 按概率选择一个瓣采样, 失败时返回None
+    fn sample(&self) -> Option<(Vec3, LobeFlags)> {
+        let wo = self.wo;
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let mut xi = rand::thread_rng().gen::<f64>();
+        let mut lobe = DIFFUSE;
+        while lobe < TRANSMISSION && xi >= self.prob[lobe] {
+            xi -= self.prob[lobe];
+            lobe += 1;
+        }
+
+        match lobe {
+            DIFFUSE => Some((
+                Vec3::rand_cos_dir(),
+                LobeFlags::DIFFUSE | LobeFlags::REFLECTION,
+            )),
+            SPECULAR | CLEARCOAT => {
+                let wh = if lobe == SPECULAR {
+                    self.distribution.sample_wh(&wo)
+                } else {
+                    sample_gtr1(self.clearcoat_alpha)
+                };
+                let wi = Vec3::reflect(&-wo, &wh);
+                if wi.z > 0.0 {
+                    Some((wi, LobeFlags::GLOSSY | LobeFlags::REFLECTION))
+                } else {
+                    None
+                }
+            }
+            _ => {
+                let wh = self.distribution.sample_wh(&wo);
+                match refract(&wo, &wh, self.eta) {
+                    Some(wi) if wi.z < 0.0 => {
+                        Some((wi, LobeFlags::GLOSSY | LobeFlags::TRANSMISSION))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl Material for Principled {
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::DIFFUSE | LobeFlags::GLOSSY | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        if self.transmissive {
+            Some(self.interior)
+        } else {
+            None
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let lobes = Lobes::new(self, r_in, rec, eta);
+        lobes.eval(&lobes.uvw.to_local(wi.unit_vector()))
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let lobes = Lobes::new(self, r_in, rec, eta);
+        let (wi, flags) = lobes.sample()?;
+        let pdf = lobes.pdf(&wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample::new(
+            lobes.uvw.local(wi),
+            lobes.eval(&wi),
+            pdf,
+            flags,
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        let lobes = Lobes::new(self, r_in, rec, eta);
+        lobes.pdf(&lobes.uvw.to_local(wi.unit_vector()))
+    }
+}
@@ -0,0 +1,126 @@
+use super::dielectric::Interior;
+use super::microfacet::{DielectricInterface, TrowbridgeReitz};
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::basic::onb::ONB;
+use crate::hittable::HitRecord;
+use crate::utility::*;
+
+// 表面粗糙的电介质(磨砂玻璃等), GGX微表面上的反射和折射
+#[derive(Copy, Clone)]
+pub struct RoughDielectric {
+    interior: Interior, // 折射率, 吸收和嵌套的优先级
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: f64) -> Self {
+        Self {
+            interior: Interior::new(ir),
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    // 与Dielectric相同, 光在内部走过distance后剩下的颜色
+    pub fn with_absorption(mut self, color: Color, distance: f64) -> Self {
+        self.interior = self.interior.with_absorption(color, distance);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.interior = self.interior.with_priority(priority);
+        self
+    }
+
+    // 不与其他电介质嵌套时, 入射一侧与透射一侧的折射率之比
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            1.0 / self.interior.ior
+        } else {
+            self.interior.ior
+        }
+    }
+
+    // 两侧折射率相同时界面不可见
+    fn index_matched(eta: f64) -> bool {
+        (eta - 1.0).abs() < 1e-6
+    }
+}
+
+impl Material for RoughDielectric {
+    fn flags(&self) -> LobeFlags {
+        let lobe = if self.distribution.is_smooth() {
+            LobeFlags::SPECULAR
+        } else {
+            LobeFlags::GLOSSY
+        };
+        lobe | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(self.interior)
+    }
+
+    // 界面的eta是法线背面与正面之比, 即透射一侧与入射一侧之比
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        if wi.near_zero() || RoughDielectric::index_matched(eta) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        let wi = uvw.to_local(wi.unit_vector());
+        if wo.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let value = DielectricInterface::new(1.0 / eta, self.distribution).eval(&wo, &wi);
+        Color::new(value, value, value)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        if RoughDielectric::index_matched(eta) {
+            return Some(BSDFSample::new_specular(
+                r_in.dir,
+                Color::new(1.0, 1.0, 1.0),
+                LobeFlags::TRANSMISSION,
+            ));
+        }
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let sample = DielectricInterface::new(1.0 / eta, self.distribution).sample(&wo)?;
+        let lobe = if sample.wi.z > 0.0 {
+            LobeFlags::REFLECTION
+        } else {
+            LobeFlags::TRANSMISSION
+        };
+        let wi = uvw.local(sample.wi);
+        let weight = Color::new(sample.weight, sample.weight, sample.weight);
+        Some(if sample.specular {
+            BSDFSample::new_specular(wi, weight, lobe)
+        } else {
+            BSDFSample::new(
+                wi,
+                weight * sample.pdf,
+                sample.pdf,
+                LobeFlags::GLOSSY | lobe,
+            )
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        let eta = ctx.eta.unwrap_or_else(|| self.eta(rec));
+        if wi.near_zero() || RoughDielectric::index_matched(eta) {
+            return 0.0;
+        }
+        let uvw = ONB::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.dir.unit_vector());
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        DielectricInterface::new(1.0 / eta, self.distribution)
+            .pdf(&wo, &uvw.to_local(wi.unit_vector()))
+    }
+}
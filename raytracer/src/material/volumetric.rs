@@ -0,0 +1,56 @@
+use super::phase::PhaseFunction;
+use super::{BSDFSample, LobeFlags, Material, ShadingContext};
+use crate::hittable::HitRecord;
+use crate::texture::{solid_color::SolidColor, Texture};
+use crate::utility::*;
+
+// 介质中的散射点, 按相函数散射, albedo为单次散射反照率
+// 与Isotropic一样不乘余弦项, 相函数本身就是概率密度, 可以精确采样
+pub struct Volumetric<P: PhaseFunction, T: Texture> {
+    phase: P,
+    albedo: T,
+}
+
+impl<P: PhaseFunction, T: Texture> Volumetric<P, T> {
+    pub fn new(phase: P, albedo: T) -> Self {
+        Self { phase, albedo }
+    }
+}
+
+impl<P: PhaseFunction> Volumetric<P, SolidColor> {
+    pub fn new_from_color(phase: P, color: Color) -> Self {
+        Self {
+            phase,
+            albedo: SolidColor::new(color),
+        }
+    }
+}
+
+fn cos_theta(r_in: &Ray, wi: &Vec3) -> f64 {
+    Vec3::dot(&r_in.dir.unit_vector(), &wi.unit_vector())
+}
+
+impl<P: PhaseFunction, T: Texture> Material for Volumetric<P, T> {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.p) * self.phase.p(cos_theta(r_in, wi))
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, ctx: &ShadingContext) -> Option<BSDFSample> {
+        let wi = self.phase.sample(&r_in.dir.unit_vector());
+        let pdf = self.phase.p(cos_theta(r_in, &wi));
+        Some(BSDFSample::new(
+            wi,
+            self.albedo.value(rec.u, rec.v, rec.p) * pdf,
+            pdf,
+            self.flags(),
+        ))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3, ctx: &ShadingContext) -> f64 {
+        self.phase.p(cos_theta(r_in, wi))
+    }
+
+    fn flags(&self) -> LobeFlags {
+        LobeFlags::DIFFUSE | LobeFlags::REFLECTION | LobeFlags::TRANSMISSION
+    }
+}
@@ -0,0 +1,603 @@
+use std::fs;
+
+use rand::Rng;
+
+use super::{Medium, MediumSample};
+use crate::basic::blackbody::blackbody;
+use crate::hittable::HitRecord;
+use crate::material::phase::PhaseFunction;
+use crate::material::volumetric::Volumetric;
+use crate::texture::noise_texture::NoiseTexture;
+use crate::texture::solid_color::SolidColor;
+use crate::utility::*;
+
+// 稀疏网格按 8x8x8 的块存储, 全为0的块不存
+const BRICK: usize = 8;
+// 上界网格每个方向最多的格子数
+const MAJORANT_RES: usize = 16;
+
+// 每个方向的块数, 最后一块可以不满
+fn brick_dims(res: [usize; 3]) -> [usize; 3] {
+    let count = |n: usize| (0..n).step_by(BRICK).len();
+    [count(res[0]), count(res[1]), count(res[2])]
+}
+
+enum Storage {
+    Dense(Vec<f32>),
+    Sparse {
+        bricks: Vec<Option<Box<[f32]>>>,
+        dims: [usize; 3], // 每个方向的块数
+    },
+}
+
+// 体素网格, 下标x变化最快, 网格覆盖 [0, 1]^3, 体素的值位于格子中心
+// 文件格式(小端序):
+//   稠密: "DGRD", u32 nx, ny, nz, 然后是 nx * ny * nz 个f32
+//   稀疏: "SGRD", u32 nx, ny, nz, u32 块数, 每块为 u32 bx, by, bz 和 512 个f32
+pub struct DensityGrid {
+    res: [usize; 3],
+    storage: Storage,
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), nx * ny * nz, "grid size mismatch");
+        Self {
+            res: [nx, ny, nz],
+            storage: Storage::Dense(data),
+        }
+    }
+
+    // f在每个体素的中心取值, 参数为 [0, 1]^3 中的坐标
+    pub fn new_from_fn<F: Fn(Point3) -> f64>(nx: usize, ny: usize, nz: usize, f: F) -> Self {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let p = Point3::new(
+                        (i as f64 + 0.5) / nx as f64,
+                        (j as f64 + 0.5) / ny as f64,
+                        (k as f64 + 0.5) / nz as f64,
+                    );
+                    data.push(f(p) as f32);
+                }
+            }
+        }
+        Self::new(nx, ny, nz, data)
+    }
+
+    // Perlin湍流生成的密度
+    pub fn new_from_noise(nx: usize, ny: usize, nz: usize, noise: &NoiseTexture) -> Self {
+        Self::new_from_fn(nx, ny, nz, |p| noise.turb(p))
+    }
+
+    pub fn new_from_file(filename: &str) -> Self {
+        let bytes = match fs::read(filename) {
+            Ok(bytes) => bytes,
+            Err(_) => panic!("Couldn't open file: {}", filename),
+        };
+        match DensityGrid::parse(&bytes) {
+            Some(grid) => grid,
+            None => panic!("Couldn't parse grid file: {}", filename),
+        }
+    }
+
+    // 文件不完整, 尺寸为0或块的下标越界时返回None
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut offset = 0_usize;
+        let mut read = |n: usize| {
+            let data = bytes.get(offset..offset.checked_add(n)?)?;
+            offset += n;
+            Some(data)
+        };
+        let read_u32 =
+            |data: &[u8]| u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+
+        let magic = read(4)?;
+        let (nx, ny, nz) = (read_u32(read(4)?), read_u32(read(4)?), read_u32(read(4)?));
+        if nx == 0 || ny == 0 || nz == 0 {
+            return None;
+        }
+        let voxels = nx.checked_mul(ny)?.checked_mul(nz)?;
+
+        match magic {
+            b"DGRD" => {
+                let data = DensityGrid::read_f32s(read(voxels.checked_mul(4)?)?);
+                Some(DensityGrid::new(nx, ny, nz, data))
+            }
+            b"SGRD" => {
+                let dims = brick_dims([nx, ny, nz]);
+                let mut bricks = vec![None; dims[0] * dims[1] * dims[2]];
+                let count = read_u32(read(4)?);
+                for _ in 0..count {
+                    let (bx, by, bz) = (read_u32(read(4)?), read_u32(read(4)?), read_u32(read(4)?));
+                    if bx >= dims[0] || by >= dims[1] || bz >= dims[2] {
+                        return None;
+                    }
+                    let data = DensityGrid::read_f32s(read(4 * BRICK * BRICK * BRICK)?);
+                    bricks[bx + dims[0] * (by + dims[1] * bz)] = Some(data.into_boxed_slice());
+                }
+                Some(Self {
+                    res: [nx, ny, nz],
+                    storage: Storage::Sparse { bricks, dims },
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn read_f32s(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    // 转为稀疏存储, 大部分是空白的网格(云, 烟雾)可以省下很多内存
+    pub fn into_sparse(self) -> Self {
+        let dims = brick_dims(self.res);
+        let mut bricks = Vec::with_capacity(dims[0] * dims[1] * dims[2]);
+        for bz in 0..dims[2] {
+            for by in 0..dims[1] {
+                for bx in 0..dims[0] {
+                    let mut data = vec![0.0; BRICK * BRICK * BRICK];
+                    let mut empty = true;
+                    for k in 0..BRICK {
+                        for j in 0..BRICK {
+                            for i in 0..BRICK {
+                                let value = self.voxel(
+                                    (bx * BRICK + i) as isize,
+                                    (by * BRICK + j) as isize,
+                                    (bz * BRICK + k) as isize,
+                                );
+                                if value != 0.0 {
+                                    empty = false;
+                                }
+                                data[i + BRICK * (j + BRICK * k)] = value as f32;
+                            }
+                        }
+                    }
+                    bricks.push(if empty {
+                        None
+                    } else {
+                        Some(data.into_boxed_slice())
+                    });
+                }
+            }
+        }
+        Self {
+            res: self.res,
+            storage: Storage::Sparse { bricks, dims },
+        }
+    }
+
+    // 网格外取0
+    fn voxel(&self, i: isize, j: isize, k: isize) -> f64 {
+        let [nx, ny, nz] = self.res;
+        if i < 0 || j < 0 || k < 0 || i >= nx as isize || j >= ny as isize || k >= nz as isize {
+            return 0.0;
+        }
+        let (i, j, k) = (i as usize, j as usize, k as usize);
+        match &self.storage {
+            Storage::Dense(data) => data[i + nx * (j + ny * k)] as f64,
+            Storage::Sparse { bricks, dims } => {
+                let brick = (i / BRICK) + dims[0] * ((j / BRICK) + dims[1] * (k / BRICK));
+                match &bricks[brick] {
+                    Some(data) => {
+                        data[i % BRICK + BRICK * (j % BRICK + BRICK * (k % BRICK))] as f64
+                    }
+                    None => 0.0,
+                }
+            }
+        }
+    }
+
+    // 三线性插值, p在 [0, 1]^3 中
+    pub fn lookup(&self, p: Point3) -> f64 {
+        let mut base = [0isize; 3];
+        let mut frac = [0.0; 3];
+        for a in 0..3 {
+            let x = p[a] * self.res[a] as f64 - 0.5;
+            let floor = x.floor();
+            base[a] = floor as isize;
+            frac[a] = x - floor;
+        }
+        let mut value = 0.0;
+        for dk in 0..2 {
+            for dj in 0..2 {
+                for di in 0..2 {
+                    let w = (if di == 0 { 1.0 - frac[0] } else { frac[0] })
+                        * (if dj == 0 { 1.0 - frac[1] } else { frac[1] })
+                        * (if dk == 0 { 1.0 - frac[2] } else { frac[2] });
+                    if w > 0.0 {
+                        value += w * self.voxel(base[0] + di, base[1] + dj, base[2] + dk);
+                    }
+                }
+            }
+        }
+        value
+    }
+
+    // [lo, hi] (单位坐标) 内插值可能取到的最大值
+    fn max_in(&self, lo: Point3, hi: Point3) -> f64 {
+        let mut range = [(0isize, 0isize); 3];
+        for a in 0..3 {
+            let n = self.res[a] as f64;
+            range[a] = (
+                (lo[a] * n - 0.5).floor() as isize,
+                (hi[a] * n - 0.5).floor() as isize + 1,
+            );
+        }
+        let mut max = 0.0f64;
+        for k in range[2].0..=range[2].1 {
+            for j in range[1].0..=range[1].1 {
+                for i in range[0].0..=range[0].1 {
+                    max = max.max(self.voxel(i, j, k));
+                }
+            }
+        }
+        max
+    }
+}
+
+// 由体素网格给出密度的非均匀介质, 网格拉伸到 [minimum, maximum] 的包围盒上
+// 消光系数为 密度 * sigma_t, 各通道相同, 可以用delta tracking精确采样自由程
+// 每个上界网格的格子内用其中密度的最大值作为上界, 空白处可以直接跳过
+pub struct GridMedium<P: PhaseFunction> {
+    density: DensityGrid,
+    minimum: Point3,
+    maximum: Point3,
+    sigma_t: f64,
+    albedo: Color,
+    majorant: Vec<f64>,
+    majorant_res: [usize; 3],
+    temperature: Option<DensityGrid>,
+    emission_scale: f64,
+    phase: Volumetric<P, SolidColor>,
+}
+
+impl<P: PhaseFunction> GridMedium<P> {
+    pub fn new(
+        density: DensityGrid,
+        minimum: Point3,
+        maximum: Point3,
+        sigma_t: f64,
+        albedo: Color,
+        phase: P,
+    ) -> Self {
+        let majorant_res = [
+            density.res[0].min(MAJORANT_RES),
+            density.res[1].min(MAJORANT_RES),
+            density.res[2].min(MAJORANT_RES),
+        ];
+        let mut majorant = Vec::with_capacity(majorant_res[0] * majorant_res[1] * majorant_res[2]);
+        for k in 0..majorant_res[2] {
+            for j in 0..majorant_res[1] {
+                for i in 0..majorant_res[0] {
+                    let cell = |a: usize, c: usize| c as f64 / majorant_res[a] as f64;
+                    majorant.push(density.max_in(
+                        Point3::new(cell(0, i), cell(1, j), cell(2, k)),
+                        Point3::new(cell(0, i + 1), cell(1, j + 1), cell(2, k + 1)),
+                    ));
+                }
+            }
+        }
+        Self {
+            density,
+            minimum,
+            maximum,
+            sigma_t,
+            albedo,
+            majorant,
+            majorant_res,
+            temperature: None,
+            emission_scale: 0.0,
+            phase: Volumetric::new_from_color(phase, Color::new(1.0, 1.0, 1.0)),
+        }
+    }
+
+    // 按温度(开尔文)发出黑体辐射, 用于火焰; 亮度与温度的四次方成正比, scale为1000K时的亮度
+    // 只有吸收的部分发光, albedo为1时没有效果
+    pub fn with_temperature(mut self, temperature: DensityGrid, scale: f64) -> Self {
+        self.temperature = Some(temperature);
+        self.emission_scale = scale;
+        self
+    }
+
+    fn to_unit(&self, p: Point3) -> Point3 {
+        let extent = self.maximum - self.minimum;
+        let d = p - self.minimum;
+        Point3::new(d.x / extent.x, d.y / extent.y, d.z / extent.z)
+    }
+
+    fn emission(&self, p: Point3) -> Color {
+        match &self.temperature {
+            Some(temperature) => {
+                let kelvin = temperature.lookup(p);
+                if kelvin <= 0.0 {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                self.emission_scale * (kelvin / 1000.0).powi(4) * blackbody(kelvin)
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // 沿光线依次经过上界网格的格子, 对每一段 [t0, t1] 以消光系数的上界(按光线参数计)调用f
+    // f返回true时停止
+    fn march<F: FnMut(f64, f64, f64) -> bool>(&self, r: &Ray, t_max: f64, mut f: F) {
+        let o = self.to_unit(r.orig);
+        let extent = self.maximum - self.minimum;
+        let d = Vec3::new(r.dir.x / extent.x, r.dir.y / extent.y, r.dir.z / extent.z);
+
+        // 与单位立方体求交
+        let mut t0 = 0.0f64;
+        let mut t1 = t_max;
+        for a in 0..3 {
+            if d[a] == 0.0 {
+                // 光线平行于该轴
+                if o[a] < 0.0 || o[a] > 1.0 {
+                    return;
+                }
+                continue;
+            }
+            let mut near = -o[a] / d[a];
+            let mut far = (1.0 - o[a]) / d[a];
+            if d[a] < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        if t0 >= t1 {
+            return;
+        }
+
+        let ray_length = r.dir.length();
+        let mut cell = [0isize; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        let mut step = [0isize; 3];
+        for a in 0..3 {
+            let n = self.majorant_res[a];
+            let g = (o[a] + t0 * d[a]) * n as f64;
+            cell[a] = (g.floor() as isize).clamp(0, n as isize - 1);
+            let dg = d[a] * n as f64;
+            if dg > 0.0 {
+                step[a] = 1;
+                delta[a] = 1.0 / dg;
+                next[a] = t0 + (cell[a] as f64 + 1.0 - g) / dg;
+            } else if dg < 0.0 {
+                step[a] = -1;
+                delta[a] = -1.0 / dg;
+                next[a] = t0 + (cell[a] as f64 - g) / dg;
+            }
+        }
+
+        let mut t = t0;
+        loop {
+            let axis = if next[0] < next[1] && next[0] < next[2] {
+                0
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            let seg_end = next[axis].min(t1);
+            let index = cell[0] as usize
+                + self.majorant_res[0]
+                    * (cell[1] as usize + self.majorant_res[1] * cell[2] as usize);
+            let sigma_maj = self.majorant[index] * self.sigma_t * ray_length;
+            if f(t, seg_end, sigma_maj) || seg_end >= t1 {
+                return;
+            }
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.majorant_res[axis] as isize {
+                return;
+            }
+            t = seg_end;
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+impl<P: PhaseFunction> Medium for GridMedium<P> {
+    // delta tracking: 按上界采样候选的碰撞点, 以 密度 / 上界 的概率接受为真实碰撞
+    // 真实碰撞处按反照率加权散射, 吸收的部分计入发光
+    fn sample(&self, r: &Ray, t_max: f64, beta: &Color) -> MediumSample {
+        let mut rng = rand::thread_rng();
+        let mut collision = None;
+        self.march(r, t_max, |t0, t1, sigma_maj| {
+            if sigma_maj <= 0.0 {
+                return false;
+            }
+            let mut t = t0;
+            loop {
+                t -= (1.0 - rng.gen::<f64>()).ln() / sigma_maj;
+                if t >= t1 {
+                    return false;
+                }
+                let p = self.to_unit(r.at(t));
+                let sigma_t = self.density.lookup(p) * self.sigma_t * r.dir.length();
+                if rng.gen::<f64>() * sigma_maj < sigma_t {
+                    collision = Some((t, p));
+                    return true;
+                }
+            }
+        });
+
+        match collision {
+            Some((t, p)) => {
+                let rec = HitRecord {
+                    p: r.at(t),
+                    normal: -r.dir.unit_vector(),
+                    mat_ptr: &self.phase,
+                    t,
+                    u: 0.0,
+                    v: 0.0,
+                    front_face: true,
+                    medium: None,
+                };
+                MediumSample {
+                    scatter: Some(rec),
+                    weight: self.albedo,
+                    emitted: (Color::new(1.0, 1.0, 1.0) - self.albedo) * self.emission(p),
+                }
+            }
+            None => MediumSample {
+                scatter: None,
+                weight: Color::new(1.0, 1.0, 1.0),
+                emitted: Color::new(0.0, 0.0, 0.0),
+            },
+        }
+    }
+
+    // ratio tracking: 每个候选碰撞点乘上 1 - 密度 / 上界, 是透射率的无偏估计
+    fn tr(&self, r: &Ray, t_max: f64) -> Color {
+        let mut rng = rand::thread_rng();
+        let mut tr = 1.0;
+        self.march(r, t_max, |t0, t1, sigma_maj| {
+            if sigma_maj <= 0.0 {
+                return false;
+            }
+            let mut t = t0;
+            loop {
+                t -= (1.0 - rng.gen::<f64>()).ln() / sigma_maj;
+                if t >= t1 {
+                    return false;
+                }
+                let sigma_t =
+                    self.density.lookup(self.to_unit(r.at(t))) * self.sigma_t * r.dir.length();
+                tr *= 1.0 - sigma_t / sigma_maj;
+                // 透射率很小时俄罗斯轮盘赌
+                if tr < 0.1 {
+                    if rng.gen::<f64>() < 0.5 {
+                        tr = 0.0;
+                        return true;
+                    }
+                    tr *= 2.0;
+                }
+            }
+        });
+        Color::new(tr, tr, tr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES: [usize; 3] = [10, 9, 17]; // 都不是BRICK的整数倍
+
+    fn test_grid() -> DensityGrid {
+        DensityGrid::new_from_fn(RES[0], RES[1], RES[2], |p| {
+            // 只有一角不为0, 稀疏存储时大部分块为空
+            (0.5 - p.length()).max(0.0) * 10.0
+        })
+    }
+
+    fn header(magic: &[u8], res: [usize; 3]) -> Vec<u8> {
+        let mut out = magic.to_vec();
+        for &n in res.iter() {
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        out
+    }
+
+    fn encode_dense(grid: &DensityGrid) -> Vec<u8> {
+        let mut out = header(b"DGRD", grid.res);
+        for k in 0..grid.res[2] {
+            for j in 0..grid.res[1] {
+                for i in 0..grid.res[0] {
+                    let value = grid.voxel(i as isize, j as isize, k as isize) as f32;
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    fn encode_sparse(grid: &DensityGrid) -> Vec<u8> {
+        let (bricks, dims) = match &grid.storage {
+            Storage::Sparse { bricks, dims } => (bricks, dims),
+            Storage::Dense(_) => panic!("not a sparse grid"),
+        };
+        let mut out = header(b"SGRD", grid.res);
+        let count = bricks.iter().filter(|b| b.is_some()).count();
+        out.extend_from_slice(&(count as u32).to_le_bytes());
+        for (n, brick) in bricks.iter().enumerate() {
+            if let Some(data) = brick {
+                let index = [n % dims[0], n / dims[0] % dims[1], n / (dims[0] * dims[1])];
+                for &c in index.iter() {
+                    out.extend_from_slice(&(c as u32).to_le_bytes());
+                }
+                for value in data.iter() {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    fn assert_same(a: &DensityGrid, b: &DensityGrid) {
+        assert_eq!(a.res, b.res);
+        for k in -1..=RES[2] as isize {
+            for j in -1..=RES[1] as isize {
+                for i in -1..=RES[0] as isize {
+                    assert_eq!(a.voxel(i, j, k), b.voxel(i, j, k));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let grid = test_grid();
+        let read = DensityGrid::parse(&encode_dense(&grid)).unwrap();
+        assert_same(&grid, &read);
+    }
+
+    #[test]
+    fn sparse_round_trip() {
+        let grid = test_grid().into_sparse();
+        let bytes = encode_sparse(&grid);
+        let read = DensityGrid::parse(&bytes).unwrap();
+        match &read.storage {
+            Storage::Sparse { bricks, .. } => {
+                assert!(bricks.iter().any(|b| b.is_none()));
+                assert!(bricks.iter().any(|b| b.is_some()));
+            }
+            Storage::Dense(_) => panic!("expected a sparse grid"),
+        }
+        assert_same(&grid, &read);
+        assert_same(&test_grid(), &read);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let dense = encode_dense(&test_grid());
+        let sparse = encode_sparse(&test_grid().into_sparse());
+        // 文件头不完整, 数据不完整
+        assert!(DensityGrid::parse(&dense[..10]).is_none());
+        assert!(DensityGrid::parse(&dense[..dense.len() - 1]).is_none());
+        assert!(DensityGrid::parse(&sparse[..sparse.len() - 1]).is_none());
+        // 尺寸为0
+        assert!(DensityGrid::parse(&header(b"DGRD", [0, 4, 4])).is_none());
+        // 块的下标越界
+        let mut bad = sparse.clone();
+        bad[20..24].copy_from_slice(&2_u32.to_le_bytes());
+        assert!(DensityGrid::parse(&bad).is_none());
+        // 未知格式
+        let mut bad = dense;
+        bad[..4].copy_from_slice(b"XXXX");
+        assert!(DensityGrid::parse(&bad).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Couldn't parse grid file")]
+    fn new_from_file_panics_on_truncated_file() {
+        let path = std::env::temp_dir().join("raytracer_truncated_grid.bin");
+        fs::write(&path, b"DGRD\x04\x00").unwrap();
+        DensityGrid::new_from_file(path.to_str().unwrap());
+    }
+}
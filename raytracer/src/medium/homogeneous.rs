@@ -0,0 +1,128 @@
+use rand::Rng;
+
+use super::{Medium, MediumSample};
+use crate::hittable::HitRecord;
+use crate::material::phase::{IsotropicPhase, PhaseFunction};
+use crate::material::volumetric::Volumetric;
+use crate::texture::solid_color::SolidColor;
+use crate::utility::*;
+
+// 均匀介质, 吸收和散射系数按RGB分别给定
+// 散射点的反照率已经计入权重, 相函数的材质取白色
+pub struct Homogeneous<P: PhaseFunction> {
+    sigma_s: Color,
+    sigma_t: Color,
+    phase: Volumetric<P, SolidColor>,
+}
+
+impl<P: PhaseFunction> Homogeneous<P> {
+    pub fn new(sigma_a: Color, sigma_s: Color, phase: P) -> Self {
+        Self {
+            sigma_s,
+            sigma_t: sigma_a + sigma_s,
+            phase: Volumetric::new_from_color(phase, Color::new(1.0, 1.0, 1.0)),
+        }
+    }
+
+    // 用密度和单次散射反照率给出介质, 如烟雾
+    pub fn new_from_density(density: f64, albedo: Color, phase: P) -> Self {
+        let sigma_s = density * albedo;
+        Self::new(
+            density * Color::new(1.0, 1.0, 1.0) - sigma_s,
+            sigma_s,
+            phase,
+        )
+    }
+}
+
+impl Homogeneous<IsotropicPhase> {
+    pub fn new_isotropic(sigma_a: Color, sigma_s: Color) -> Self {
+        Self::new(sigma_a, sigma_s, IsotropicPhase)
+    }
+}
+
+// 某个通道消光系数为0时避免出现 0 * inf
+fn transmittance(sigma_t: f64, dist: f64) -> f64 {
+    if sigma_t == 0.0 {
+        1.0
+    } else {
+        (-sigma_t * dist).exp()
+    }
+}
+
+impl<P: PhaseFunction> Homogeneous<P> {
+    fn tr_dist(&self, dist: f64) -> Color {
+        Color::new(
+            transmittance(self.sigma_t.x, dist),
+            transmittance(self.sigma_t.y, dist),
+            transmittance(self.sigma_t.z, dist),
+        )
+    }
+}
+
+impl<P: PhaseFunction> Medium for Homogeneous<P> {
+    // 按吞吐量的比例选择一个通道采样自由程, 权重按各通道概率密度的加权平均计算
+    fn sample(&self, r: &Ray, t_max: f64, beta: &Color) -> MediumSample {
+        let mut rng = rand::thread_rng();
+        let total = beta.x + beta.y + beta.z;
+        let prob = if total > 0.0 {
+            *beta / total
+        } else {
+            Color::new(1.0, 1.0, 1.0) / 3.0
+        };
+        let u = rng.gen::<f64>();
+        let channel = if u < prob.x {
+            self.sigma_t.x
+        } else if u < prob.x + prob.y {
+            self.sigma_t.y
+        } else {
+            self.sigma_t.z
+        };
+        let ray_length = r.dir.length();
+        let dist_max = t_max * ray_length;
+        let dist = if channel == 0.0 {
+            f64::INFINITY
+        } else {
+            -(1.0 - rng.gen::<f64>()).ln() / channel
+        };
+
+        if dist < dist_max {
+            let tr = self.tr_dist(dist);
+            let density = prob * self.sigma_t * tr;
+            let pdf = density.x + density.y + density.z;
+            let t = dist / ray_length;
+            let rec = HitRecord {
+                p: r.at(t),
+                normal: -r.dir.unit_vector(),
+                mat_ptr: &self.phase,
+                t,
+                u: 0.0,
+                v: 0.0,
+                front_face: true,
+                medium: None,
+            };
+            MediumSample {
+                scatter: Some(rec),
+                weight: self.sigma_s * tr / pdf,
+                emitted: Color::new(0.0, 0.0, 0.0),
+            }
+        } else {
+            let tr = self.tr_dist(dist_max);
+            let density = prob * tr;
+            let pdf = density.x + density.y + density.z;
+            MediumSample {
+                scatter: None,
+                weight: if pdf == 0.0 {
+                    Color::new(0.0, 0.0, 0.0)
+                } else {
+                    tr / pdf
+                },
+                emitted: Color::new(0.0, 0.0, 0.0),
+            }
+        }
+    }
+
+    fn tr(&self, r: &Ray, t_max: f64) -> Color {
+        self.tr_dist(t_max * r.dir.length())
+    }
+}
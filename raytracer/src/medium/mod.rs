@@ -0,0 +1,23 @@
+pub mod grid;
+pub mod homogeneous;
+
+use crate::hittable::HitRecord;
+use crate::utility::*;
+
+// 在介质中采样自由程的结果
+pub struct MediumSample<'a> {
+    pub scatter: Option<HitRecord<'a>>, // 散射点, 其材质为相函数; None表示先到达了t_max
+    pub weight: Color,                  // 路径吞吐量的权重
+    pub emitted: Color,                 // 介质自身发出的光(如火焰), 已经除以采样的概率
+}
+
+// 参与介质, 附着在封闭曲面的内部或充满整个场景
+// 光线的方向不必是单位向量, t都是光线的参数
+pub trait Medium: Send + Sync {
+    // 在光线的 [0, t_max] 上采样与介质的一次作用
+    // beta为到这里为止的路径吞吐量, 用来选择按哪个通道采样, 避免各通道的权重在长路径上相差越来越大
+    fn sample(&self, r: &Ray, t_max: f64, beta: &Color) -> MediumSample;
+
+    // 光线在 [0, t_max] 上的透射率, 用于光源采样的阴影光线
+    fn tr(&self, r: &Ray, t_max: f64) -> Color;
+}
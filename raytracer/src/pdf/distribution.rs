@@ -0,0 +1,160 @@
+// 分段常数的一维分布, 按函数值的大小采样 [0, 1) 上的点
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64, // 函数在 [0, 1) 上的积分
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // 函数处处为0时退化为均匀分布
+            *c = if func_int == 0.0 {
+                i as f64 / n as f64
+            } else {
+                *c / func_int
+            };
+        }
+        Self {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn func_int(&self) -> f64 {
+        self.func_int
+    }
+
+    // 返回采样点, 该点的概率密度和所在的段
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.count() - 1);
+        let mut du = u - self.cdf[offset];
+        if self.cdf[offset + 1] - self.cdf[offset] > 0.0 {
+            du /= self.cdf[offset + 1] - self.cdf[offset];
+        }
+        let x = (offset as f64 + du) / self.count() as f64;
+        (x, self.pdf(offset), offset)
+    }
+
+    // 第offset段上的概率密度
+    pub fn pdf(&self, offset: usize) -> f64 {
+        if self.func_int == 0.0 {
+            1.0
+        } else {
+            self.func[offset].abs() / self.func_int
+        }
+    }
+}
+
+// 二维分布, 先按边缘分布选行, 再在该行的条件分布中选列
+#[derive(Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // func按行存放, 共height行, 每行width个
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.func_int()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    // 返回 (u, v) 及其概率密度, u为列方向, v为行方向
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let col = ((u * width as f64) as usize).min(width - 1);
+        let row = ((v * height as f64) as usize).min(height - 1);
+        self.marginal.pdf(row) * self.conditional[row].pdf(col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_inverts_cdf() {
+        let dist = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        assert!((dist.func_int() - 2.0).abs() < 1e-12);
+        let n = dist.count() as f64;
+        let mut last_x = -1.0;
+        for i in 0..100 {
+            let u = i as f64 / 100.0;
+            let (x, pdf, offset) = dist.sample_continuous(u);
+            // 采样点落在所在段内, 且不会落在函数为0的段
+            assert!(x >= offset as f64 / n && x < (offset + 1) as f64 / n);
+            assert_ne!(offset, 2);
+            assert!(x > last_x);
+            last_x = x;
+            assert_eq!(pdf, dist.pdf(offset));
+            // 把采样点代回CDF得到原来的u
+            let cdf = dist.cdf[offset]
+                + (x * n - offset as f64) * (dist.cdf[offset + 1] - dist.cdf[offset]);
+            assert!((cdf - u).abs() < 1e-12);
+        }
+        let total: f64 = (0..dist.count()).map(|i| dist.pdf(i) / n).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn zero_function_is_uniform() {
+        let dist = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, offset) = dist.sample_continuous(0.6);
+        assert!((x - 0.6).abs() < 1e-12);
+        assert_eq!((pdf, offset), (1.0, 2));
+    }
+
+    #[test]
+    fn sample_2d_matches_pdf() {
+        let (width, height) = (4, 3);
+        let func: Vec<f64> = (0..width * height).map(|i| (i % 5) as f64).collect();
+        let dist = Distribution2D::new(&func, width, height);
+
+        let mut total = 0.0;
+        for row in 0..height {
+            for col in 0..width {
+                let u = (col as f64 + 0.5) / width as f64;
+                let v = (row as f64 + 0.5) / height as f64;
+                total += dist.pdf(u, v) / (width * height) as f64;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-12);
+
+        for i in 0..20 {
+            for j in 0..20 {
+                let (u0, u1) = ((i as f64 + 0.5) / 20.0, (j as f64 + 0.5) / 20.0);
+                let ((u, v), pdf) = dist.sample_continuous(u0, u1);
+                assert!(pdf > 0.0);
+                assert!((pdf - dist.pdf(u, v)).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use super::PDF;
+use crate::basic::onb::ONB;
+use crate::material::microfacet::TrowbridgeReitz;
+use crate::utility::*;
+
+// 按GGX可见法线分布采样反射方向
+#[derive(Copy, Clone)]
+pub struct GgxPDF {
+    uvw: ONB,
+    wo: Vec3, // 局部坐标系中的出射方向(指向观察者)
+    distribution: TrowbridgeReitz,
+}
+
+impl GgxPDF {
+    pub fn new(n: Vec3, wo: Vec3, distribution: TrowbridgeReitz) -> Self {
+        let uvw = ONB::build_from_w(n);
+        Self {
+            wo: uvw.to_local(wo.unit_vector()),
+            uvw,
+            distribution,
+        }
+    }
+}
+
+impl PDF for GgxPDF {
+    fn value(&self, dir: &Vec3) -> f64 {
+        let wi = self.uvw.to_local(dir.unit_vector());
+        let wh = self.wo + wi;
+        if self.wo.z <= 0.0 || wh.near_zero() {
+            return 0.0;
+        }
+        let wh = wh.unit_vector();
+        self.distribution.pdf_wh(&self.wo, &wh) / (4.0 * Vec3::dot(&self.wo, &wh).abs())
+    }
+
+    fn generate(&self) -> Vec3 {
+        let wh = self.distribution.sample_wh(&self.wo);
+        self.uvw.local(Vec3::reflect(&-self.wo, &wh))
+    }
+}
@@ -5,20 +5,21 @@ use crate::{hittable::Hittable, utility::*};
 pub struct HittablePDF<'a, T: Hittable> {
     obj: &'a T,
     orig: Point3,
+    time: f64,
 }
 
 impl<'a, T: Hittable> HittablePDF<'a, T> {
-    pub fn new(obj: &'a T, orig: Point3) -> Self {
-        Self { obj, orig }
+    pub fn new(obj: &'a T, orig: Point3, time: f64) -> Self {
+        Self { obj, orig, time }
     }
 }
 
 impl<'a, T: Hittable> PDF for HittablePDF<'a, T> {
     fn value(&self, dir: &Vec3) -> f64 {
-        self.obj.pdf_value(&self.orig, dir)
+        self.obj.pdf_value(&self.orig, dir, self.time)
     }
 
     fn generate(&self) -> Vec3 {
-        self.obj.random(self.orig)
+        self.obj.random(self.orig, self.time)
     }
 }
@@ -1,4 +1,6 @@
 pub mod cosine_pdf;
+pub mod distribution;
+pub mod ggx_pdf;
 pub mod hittable_pdf;
 pub mod mixture_pdf;
 
@@ -9,3 +11,13 @@ pub trait PDF {
 
     fn generate(&self) -> Vec3;
 }
+
+impl<T: PDF + ?Sized> PDF for Box<T> {
+    fn value(&self, dir: &Vec3) -> f64 {
+        self.as_ref().value(dir)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.as_ref().generate()
+    }
+}
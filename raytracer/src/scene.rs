@@ -1,24 +1,43 @@
+use crate::basic::atmosphere::Atmosphere;
+use crate::basic::background::Background;
 use crate::basic::camera::Camera;
-use crate::bvh::bvh_node::BvhNode;
+use crate::basic::mat4::Mat4;
+use crate::basic::sky::PreethamSky;
+use crate::bvh::light_bvh::LightBvh;
+use crate::bvh::linear_bvh::LinearBvh;
+use crate::bvh::tlas::Tlas;
 use crate::hittable::constant_medium::ConstantMedium;
+use crate::hittable::delta_light::{DirectionalLight, PointLight, SpotLight};
+use crate::hittable::environment::EnvironmentLight;
 use crate::hittable::hittable_list;
 use crate::hittable::instance::flip_face::FlipFace;
+use crate::hittable::instance::mesh_instance::{MeshHandle, MeshInstance};
 use crate::hittable::instance::motion::Motion;
-use crate::hittable::instance::rotation::{RotationX, RotationZ};
+use crate::hittable::instance::transform::Transform;
 use crate::hittable::instance::{rotation::RotationY, translation::Translation};
+use crate::hittable::medium_boundary::MediumBoundary;
 use crate::hittable::{
     aarect::{XYRect, XZRect, YZRect},
     cuboid::Cuboid,
     obj_model::ObjModel,
     sphere::Sphere,
+    subsurface::Subsurface,
     triangle::Triangle,
 };
 use crate::hittable::{hittable_list::HittableList, Hittable};
+use crate::material::layered::Layered;
+use crate::material::mix::Mix;
+use crate::material::oren_nayar::OrenNayar;
+use crate::material::phase::{DoubleHenyeyGreenstein, HenyeyGreenstein, IsotropicPhase, Rayleigh};
+use crate::material::principled::Principled;
+use crate::material::rough_dielectric::RoughDielectric;
 use crate::material::{self, lambertian};
 use crate::material::{
-    dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian, metal::Metal,
-    Material,
+    conductor::Conductor, dielectric::Dielectric, diffuse_light::DiffuseLight,
+    lambertian::Lambertian, metal::Metal, Material,
 };
+use crate::medium::grid::{DensityGrid, GridMedium};
+use crate::medium::homogeneous::Homogeneous;
 use crate::texture::checker_texture::CheckerTexture;
 use crate::texture::image_texture::{ImageTexture, RandImageTexture};
 use crate::texture::noise_texture::NoiseTexture;
@@ -101,7 +120,7 @@ pub fn random_ball_scene(aspect_ratio: f64) -> (HittableList, Camera) {
         material3,
     )));
 
-    let bvh = Arc::new(BvhNode::new_from_list(&mut world, 0.0, 1.0));
+    let bvh = Arc::new(LinearBvh::new_from_list(&world, 0.0, 1.0));
     new_world.add(bvh);
 
     // Camera
@@ -144,7 +163,7 @@ pub fn two_spheres(aspect_ratio: f64) -> (HittableList, Camera) {
         mat,
     )));
 
-    let bvh = BvhNode::new_from_list(&mut objects, 0.0, 1.0);
+    let bvh = LinearBvh::new_from_list(&objects, 0.0, 1.0);
     world.add(Arc::new(bvh));
 
     // Camera
@@ -264,7 +283,7 @@ pub fn cornell_box(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
         white,
     );
     let box1 = RotationY::new(box1, 15.);
-    let box1 = Translation::new(box1, Point3::new(265., 0., 295.));
+    let box1 = Translation::new(box1, Point3::new(265., 0.1, 295.));
     // objects.add(Arc::new(box1));
 
     // let glass = Dielectric::new(1.5);
@@ -280,10 +299,11 @@ pub fn cornell_box(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
         white,
     );
     let box2 = RotationY::new(box2, -18.);
-    let box2 = Translation::new(box2, Point3::new(130., 0., 65.));
+    let box2 = Translation::new(box2, Point3::new(130., 0.1, 65.));
     // objects.add(Arc::new(box2));
 
     // Volume Smoke
+    // 盒子稍微离开地面, 烟雾的边界与地面重合时分不清光线是否已经离开烟雾
     objects.add(Arc::new(ConstantMedium::new_from_color(
         box1,
         0.01,
@@ -355,7 +375,7 @@ pub fn book2_final_scene(aspect_ratio: f64) -> (HittableList, Camera) {
 
     let mut objects = HittableList::default();
 
-    objects.add(Arc::new(BvhNode::new_from_list(&mut boxes1, 0.0, 1.0)));
+    objects.add(Arc::new(LinearBvh::new_from_list(&boxes1, 0.0, 1.0)));
 
     let light = DiffuseLight::new_form_color(Color::new(7., 7., 7.));
     objects.add(Arc::new(XZRect::new(123., 423., 147., 412., 554., light)));
@@ -377,18 +397,16 @@ pub fn book2_final_scene(aspect_ratio: f64) -> (HittableList, Camera) {
         Metal::new(Color::new(0.8, 0.8, 0.9), 1.0),
     )));
 
+    // 装着蓝色烟雾的玻璃球, 玻璃表面就是烟雾的边界
     let boundary = Sphere::new(Point3::new(360., 150., 145.), 70., Dielectric::new(1.5));
-    objects.add(Arc::new(boundary.clone()));
-    objects.add(Arc::new(ConstantMedium::new_from_color(
+    objects.add(Arc::new(MediumBoundary::new_with_surface(
         boundary,
-        0.2,
-        Color::new(0.2, 0.4, 0.9),
+        Homogeneous::new_from_density(0.2, Color::new(0.2, 0.4, 0.9), IsotropicPhase),
     )));
-    let boundary = Sphere::new(Point3::new(0., 0., 0.), 5000., Dielectric::new(1.5));
-    objects.add(Arc::new(ConstantMedium::new_from_color(
-        boundary,
+    objects.set_medium(Arc::new(Homogeneous::new_from_density(
         0.0001,
         Color::new(1.0, 1.0, 1.0),
+        IsotropicPhase,
     )));
 
     let emat = Lambertian::new(ImageTexture::new_form_file("images/earthmap.jpg"));
@@ -416,7 +434,7 @@ pub fn book2_final_scene(aspect_ratio: f64) -> (HittableList, Camera) {
         )));
     }
 
-    let bvh = BvhNode::new_from_list(&mut boxes2, 0.0, 1.0);
+    let bvh = LinearBvh::new_from_list(&boxes2, 0.0, 1.0);
     objects.add(Arc::new(Translation::new(
         RotationY::new(bvh, 15.),
         Vec3::new(-100., 270., 395.),
@@ -452,8 +470,10 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
 
     // 灯光
     let light = DiffuseLight::new_form_color(Color::new(10., 10., 10.));
-    let light_ball = XZRect::new(400., 1400., -200., 1400., 1800., light);
-    objects.add(Arc::new(FlipFace::new(light_ball.clone())));
+    let light_ball = Arc::new(FlipFace::new(XZRect::new(
+        400., 1400., -200., 1400., 1800., light,
+    )));
+    objects.add(light_ball.clone());
     // let light_ball = Sphere::new(
     //     Point3::new(200., 1800., 200.),
     //     500.,
@@ -477,7 +497,10 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
     let cube_diff_mat = Lambertian::new_form_color(Color::new(0.73, 0.73, 0.73));
     let cube_diff = ObjModel::new_from_file("models/cube_diff.obj", 10., cube_diff_mat);
     let offset = Vec3::new(1488., 0., 800.);
-    let cube_diff = Translation::new(RotationY::new(cube_diff, 180.), offset);
+    let cube_diff = Transform::new(
+        cube_diff,
+        Mat4::translation(offset) * Mat4::rotation_y(180.),
+    );
     objects.add(Arc::new(cube_diff));
 
     // 一个金属柱子
@@ -487,8 +510,8 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
         Point3::new(150., 400., 150.),
         cube_spec_mat,
     );
-    let cube_spec = RotationY::new(cube_spec, 0.);
-    let cube_spec = Translation::new(cube_spec, Point3::new(1800., 0., 1000.));
+    let transform = Mat4::translation(Vec3::new(1800., 0., 1000.)) * Mat4::rotation_y(0.);
+    let cube_spec = Transform::new(cube_spec, transform);
     objects.add(Arc::new(cube_spec));
 
     // 一个金属柱子
@@ -498,8 +521,8 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
         Point3::new(150., 550., 150.),
         cube_spec_mat,
     );
-    let cube_spec = RotationY::new(cube_spec, -18.);
-    let cube_spec = Translation::new(cube_spec, Point3::new(800., 0., 500.));
+    let transform = Mat4::translation(Vec3::new(800., 0., 500.)) * Mat4::rotation_y(-18.);
+    let cube_spec = Transform::new(cube_spec, transform);
     objects.add(Arc::new(cube_spec));
 
     // 镜面
@@ -521,129 +544,135 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
     let head = ObjModel::new_from_file("models/1.obj", 10., green_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1788., 150., 700.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), 180.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(180.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 2: cattle
     let head = ObjModel::new_from_file("models/2.obj", 10., green_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1388., 550., 400.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), -90.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(-90.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 3: tiger
     let head = ObjModel::new_from_file("models/3.obj", 10., grey_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(150., 150., 150.);
-    let head = Translation::new(
-        RotationZ::new(
-            RotationY::new(RotationX::new(Translation::new(head, offset1), 0.), -90.),
-            -90.,
-        ),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_z(-90.)
+        * Mat4::rotation_y(-90.)
+        * Mat4::rotation_x(0.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 4: rabbit
     let head = ObjModel::new_from_file("models/4.obj", 10., grey_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1800., 120., 200.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), 80.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(80.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 5: dragon
     let head = ObjModel::new_from_file("models/5.obj", 10., yellow_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1188., 850., 700.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), 180.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(180.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 6: snake
     let head = ObjModel::new_from_file("models/6.obj", 10., yellow_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(460., 350., 400.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), 0.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(0.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 7: horse
     let head = ObjModel::new_from_file("models/7.obj", 10., green_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1000., 100., 350.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), -30.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(-30.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 8: goat
     let head = ObjModel::new_from_file("models/8.obj", 10., green_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1800., 570., 1000.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), -100.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(-100.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 9: monkey
     let head = ObjModel::new_from_file("models/9.obj", 10., yellow_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(1450., 50., 200.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), -140.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(-140.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 10: chicken
     let head = ObjModel::new_from_file("models/10.obj", 10., green_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(2100., 150., 800.);
-    let head = Translation::new(
-        RotationZ::new(
-            RotationY::new(
-                RotationZ::new(RotationX::new(Translation::new(head, offset1), -90.), -90.),
-                180.,
-            ),
-            180.,
-        ),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_z(180.)
+        * Mat4::rotation_y(180.)
+        * Mat4::rotation_z(-90.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 11: dog
     let head = ObjModel::new_from_file("models/11.obj", 10., grey_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(150., 180., 800.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), 200.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(200.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // 12: pig
     let head = ObjModel::new_from_file("models/12.obj", 10., grey_mat1);
     let offset1 = Vec3::new(0., 0., 0.) - head.center;
     let offset2 = Vec3::new(800., 600., 500.);
-    let head = Translation::new(
-        RotationY::new(RotationX::new(Translation::new(head, offset1), -90.), -90.),
-        offset2,
-    );
+    let transform = Mat4::translation(offset2)
+        * Mat4::rotation_y(-90.)
+        * Mat4::rotation_x(-90.)
+        * Mat4::translation(offset1);
+    let head = Transform::new(head, transform);
     objects.add(Arc::new(head));
 
     // Add a bit fog
@@ -655,10 +684,10 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
     // )));
 
     // Bvh
-    world.add(Arc::new(BvhNode::new_from_list(&mut objects, 0.0, 1.0)));
+    world.add(Arc::new(LinearBvh::new_from_list(&objects, 0.0, 1.0)));
 
     // Lights
-    lights.add(Arc::new(light_ball));
+    lights.add(light_ball);
 
     // Camera
     let look_from = Point3::new(900.0, 1250.0, -1300.0);
@@ -681,3 +710,662 @@ pub fn final_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
 
     (world, lights, cam)
 }
+
+// 棋盘格地面
+fn add_checker_ground(objects: &mut HittableList, odd: Color, even: Color) {
+    let ground = Lambertian::new(CheckerTexture::new_form_color(odd, even));
+    objects.add(Arc::new(XZRect::new(-50., 50., -50., 50., 0., ground)));
+}
+
+// 面光源既要能被光线打到, 也要加入lights列表供光源采样
+fn add_area_light<H: Hittable + 'static>(
+    objects: &mut HittableList,
+    lights: &mut HittableList,
+    light: H,
+) {
+    let light: Arc<dyn Hittable> = Arc::new(light);
+    objects.add(light.clone());
+    lights.add(light);
+}
+
+// 高度为y, 朝下发光的白色矩形面光源
+fn ceiling_light(
+    x0: f64,
+    x1: f64,
+    z0: f64,
+    z1: f64,
+    y: f64,
+    intensity: f64,
+) -> FlipFace<XZRect<DiffuseLight<SolidColor>>> {
+    FlipFace::new(XZRect::new(
+        x0,
+        x1,
+        z0,
+        z1,
+        y,
+        DiffuseLight::new_form_color(Color::new(1., 1., 1.)).with_intensity(intensity),
+    ))
+}
+
+// 展示材质时一排六个球中的第col个, 放在z处
+fn gallery_sphere<M: Material + Send + Sync + 'static>(
+    col: usize,
+    z: f64,
+    mat: M,
+) -> Arc<dyn Hittable> {
+    Arc::new(Sphere::new(
+        Point3::new(-6.25 + 2.5 * col as f64, 1., z),
+        1.,
+        mat,
+    ))
+}
+
+// 视场角40度, 没有景深的相机
+fn demo_camera(look_from: Point3, look_at: Point3, aspect_ratio: f64) -> Camera {
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = 10.0;
+    let aperture = 0.0;
+
+    Camera::new(
+        look_from,
+        look_at,
+        vup,
+        40.0,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    )
+}
+
+// 实例化: 1000个茶壶共享同一份网格和BVH
+pub fn teapot_forest(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
+    // World
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let ground = Lambertian::new_form_color(Color::new(0.48, 0.83, 0.53));
+    world.add(Arc::new(XZRect::new(
+        -10000., 10000., -10000., 10000., 0., ground,
+    )));
+
+    let light = DiffuseLight::new_form_color(Color::new(4., 4., 4.));
+    add_area_light(
+        &mut world,
+        &mut lights,
+        FlipFace::new(XZRect::new(-1000., 1000., -1000., 1000., 2000., light)),
+    );
+
+    let teapot_mat = Lambertian::new_form_color(Color::new(0.73, 0.73, 0.73));
+    let teapot: MeshHandle = Arc::new(ObjModel::new_from_file("models/teapot.obj", 1., teapot_mat));
+
+    let mut rng = rand::thread_rng();
+    let mut instances = Vec::new();
+    for i in 0..40 {
+        for j in 0..25 {
+            // 茶壶模型的底部在 y = -40 处
+            let scale = rng.gen_range(0.5..0.9);
+            let offset = Vec3::new(
+                -2000. + i as f64 * 100. + rng.gen_range(-15.0..15.0),
+                40. * scale,
+                j as f64 * 100. + rng.gen_range(-15.0..15.0),
+            );
+            let transform = Mat4::translation(offset)
+                * Mat4::rotation_y(rng.gen_range(0.0..360.0))
+                * Mat4::uniform_scale(scale);
+            let instance = MeshInstance::new(teapot.clone(), transform);
+            // 一部分茶壶换成金属材质
+            if rng.gen::<f64>() < 0.3 {
+                let albedo = Color::rand_vec_range(0.5, 1.0);
+                instances.push(instance.with_material(Metal::new(albedo, 0.2)));
+            } else {
+                instances.push(instance);
+            }
+        }
+    }
+    world.add(Arc::new(Tlas::new(instances, 0.0, 1.0)));
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 400.0, -600.0),
+        Point3::new(0.0, 0.0, 600.0),
+        aspect_ratio,
+    );
+
+    (world, lights, cam)
+}
+
+// 光源: 几百盏面光源用光源BVH按估计贡献选择, 点光源/聚光灯/月光放在同一个列表里
+pub fn lights_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
+    // World
+    let mut world = HittableList::default();
+    let mut objects = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let ground = Lambertian::new_form_color(Color::new(0.73, 0.73, 0.73));
+    world.add(Arc::new(XZRect::new(
+        -10000., 10000., -10000., 10000., 0., ground,
+    )));
+
+    // 一盏很亮的主光源
+    let key_light = DiffuseLight::new_form_color(Color::new(15., 15., 15.));
+    add_area_light(
+        &mut objects,
+        &mut lights,
+        FlipFace::new(XZRect::new(-100., 100., 400., 600., 800., key_light)),
+    );
+
+    // 几百盏颜色和亮度各不相同的小灯
+    let mut rng = rand::thread_rng();
+    for i in 0..20 {
+        for j in 0..20 {
+            let center = Point3::new(
+                -950. + i as f64 * 100. + rng.gen_range(-30.0..30.0),
+                rng.gen_range(5.0..30.0),
+                j as f64 * 100. + rng.gen_range(-30.0..30.0),
+            );
+            let intensity = rng.gen_range(1.0..20.0);
+            let light = DiffuseLight::new_form_color(Color::rand_vec_range(0.2, 1.0) * intensity);
+            add_area_light(&mut objects, &mut lights, Sphere::new(center, 5., light));
+        }
+    }
+
+    for _ in 0..30 {
+        let center = Point3::new(
+            rng.gen_range(-900.0..900.0),
+            60.,
+            rng.gen_range(0.0..1900.0),
+        );
+        let mat = Lambertian::new_form_color(Color::rand_vec_range(0.3, 0.9));
+        objects.add(Arc::new(Sphere::new(center, 60., mat)));
+    }
+    world.add(Arc::new(LinearBvh::new_from_list(&objects, 0.0, 1.0)));
+
+    // 点光源, 聚光灯和平行光不在场景中, 只能通过光源采样照亮物体
+    lights.add(Arc::new(PointLight::new(
+        Point3::new(-500., 150., 300.),
+        Color::new(4e5, 3e5, 2e5),
+    )));
+    lights.add(Arc::new(SpotLight::new(
+        Point3::new(400., 500., 200.),
+        Point3::new(400., 0., 900.),
+        Color::new(2e6, 2e6, 2.5e6),
+        20.,
+        12.,
+    )));
+    lights.add(Arc::new(DirectionalLight::new_with_angle(
+        Vec3::new(-1., -2., 1.),
+        Color::new(0.1, 0.1, 0.15),
+        2.0,
+    )));
+
+    // 能按方向采样的光源放进光源BVH, 其余的直接放在列表里
+    let light_tree = LightBvh::build_light_list(&lights);
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 500.0, -700.0),
+        Point3::new(0.0, 0.0, 800.0),
+        aspect_ratio,
+    );
+
+    (world, light_tree, cam)
+}
+
+// 天空的三种来源, 都既是背景也是光源
+pub enum SkyModel {
+    EnvironmentMap, // 环境贴图, 换成.hdr文件即可用拍摄的HDRI打光
+    Preetham,       // Preetham解析天空模型
+    Atmosphere,     // 单次散射的大气模型, 远处的物体有空气透视
+}
+
+// 天空: 近处三个球, 远处两排越来越远的高楼, 大小与距离成正比, 看上去差不多大
+pub fn sky_scene(
+    aspect_ratio: f64,
+    model: SkyModel,
+) -> (HittableList, HittableList, Camera, Arc<dyn Background>) {
+    // World
+    let mut objects = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let ground = Lambertian::new_form_color(Color::new(0.3, 0.35, 0.25));
+    objects.add(Arc::new(XZRect::new(
+        -20000., 20000., -20000., 20000., 0., ground,
+    )));
+
+    let gold = Metal::new(Color::new(0.9, 0.75, 0.4), 0.05);
+    let glass = Dielectric::new(1.5);
+    let clay = Lambertian::new_form_color(Color::new(0.8, 0.3, 0.2));
+    objects.add(Arc::new(Sphere::new(Point3::new(-2.2, 1., 0.), 1., gold)));
+    objects.add(Arc::new(Sphere::new(Point3::new(0., 1., 0.), 1., glass)));
+    objects.add(Arc::new(Sphere::new(Point3::new(2.2, 1., 0.), 1., clay)));
+
+    let wall = Lambertian::new_form_color(Color::new(0.6, 0.6, 0.6));
+    for i in 0..6 {
+        let dist = 40.0 * 2.5_f64.powi(i);
+        let (w, h) = (0.08 * dist, 0.25 * dist);
+        for &side in [-1.0, 1.0].iter() {
+            let x = side * (0.1 + 0.12 * i as f64) * dist;
+            objects.add(Arc::new(Cuboid::new(
+                Point3::new(x - w / 2.0, 0.0, -dist - w / 2.0),
+                Point3::new(x + w / 2.0, h, -dist + w / 2.0),
+                wall,
+            )));
+        }
+    }
+
+    let background: Arc<dyn Background> = match model {
+        SkyModel::EnvironmentMap => {
+            let env = Arc::new(EnvironmentLight::new_from_file(
+                "images/earthmap.jpg",
+                90.,
+                1.0,
+            ));
+            lights.add(env.clone());
+            env
+        }
+        SkyModel::Preetham => {
+            // 傍晚的天空和太阳
+            let sky = PreethamSky::new(Vec3::new(1., 0.25, 1.), 3.0, 0.05);
+            let sky_light = Arc::new(sky.to_environment(512, 256));
+            // sky_light.save_to_file("output/sky.hdr");
+            lights.add(sky_light.clone());
+            lights.add(Arc::new(sky.sun_light(3.0)));
+            sky_light
+        }
+        SkyModel::Atmosphere => {
+            // 一个单位为10米, 最远的楼在40公里外
+            let atmosphere =
+                Atmosphere::new(Vec3::new(-1., 0.6, -2.), 5.0).with_meters_per_unit(10.0);
+            lights.add(Arc::new(atmosphere.to_environment(256, 128)));
+            lights.add(Arc::new(atmosphere.sun_light()));
+            Arc::new(atmosphere)
+        }
+    };
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 3.0, 10.0),
+        Point3::new(0.0, 4.0, -100.0),
+        aspect_ratio,
+    );
+
+    (objects, lights, cam, background)
+}
+
+// 表面材质: 后排为金属和粗糙玻璃, 中排为Principled, 前排为漫反射, 清漆和混合材质
+pub fn material_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
+    // World
+    let mut objects = HittableList::default();
+    let mut lights = HittableList::default();
+
+    add_checker_ground(
+        &mut objects,
+        Color::new(0.2, 0.2, 0.2),
+        Color::new(0.8, 0.8, 0.8),
+    );
+    let backdrop = Lambertian::new_form_color(Color::new(0.8, 0.3, 0.2));
+    objects.add(Arc::new(XYRect::new(-50., 50., 0., 50., -7., backdrop)));
+
+    // 粗糙度递增的金, 铜, 铝, 加随机扰动的金属, 光滑和磨砂的玻璃
+    objects.add(gallery_sphere(0, -4., Conductor::new_gold(0.05)));
+    objects.add(gallery_sphere(1, -4., Conductor::new_copper(0.2)));
+    objects.add(gallery_sphere(2, -4., Conductor::new_aluminium(0.4)));
+    objects.add(gallery_sphere(
+        3,
+        -4.,
+        Metal::new(Color::new(0.8, 0.85, 0.88), 0.3),
+    ));
+    objects.add(gallery_sphere(4, -4., RoughDielectric::new(1.5, 0.0)));
+    objects.add(gallery_sphere(5, -4., RoughDielectric::new(1.5, 0.3)));
+
+    // 塑料, 车漆(金属底色上加一层清漆), 拉丝金属, 天鹅绒, 磨砂玻璃, 涂了清漆的木头
+    let red = Color::new(0.8, 0.1, 0.1);
+    objects.add(gallery_sphere(
+        0,
+        0.,
+        Principled::new(red).with_roughness(0.3),
+    ));
+    objects.add(gallery_sphere(
+        1,
+        0.,
+        Principled::new(red)
+            .with_metallic(0.8)
+            .with_roughness(0.4)
+            .with_clearcoat(1.0),
+    ));
+    objects.add(gallery_sphere(
+        2,
+        0.,
+        Principled::new(Color::new(0.9, 0.9, 0.9))
+            .with_metallic(1.0)
+            .with_roughness(0.4)
+            .with_anisotropic(0.8),
+    ));
+    objects.add(gallery_sphere(
+        3,
+        0.,
+        Principled::new(Color::new(0.2, 0.05, 0.3))
+            .with_roughness(1.0)
+            .with_sheen(1.0)
+            .with_sheen_tint(1.0),
+    ));
+    objects.add(gallery_sphere(
+        4,
+        0.,
+        Principled::new(Color::new(1.0, 1.0, 1.0))
+            .with_transmission(1.0)
+            .with_roughness(0.2),
+    ));
+    objects.add(gallery_sphere(
+        5,
+        0.,
+        Layered::new(Lambertian::new(NoiseTexture::new(4.)), 1.5, 0.1)
+            .with_thickness(0.5)
+            .with_tint(Color::new(0.85, 0.55, 0.25)),
+    ));
+
+    // Lambertian和Oren-Nayar的陶土, 上漆的塑料, 生锈的铜, 磨损露出金属的红漆, 金和白色漫反射各占一半
+    let clay = Color::new(0.75, 0.45, 0.3);
+    let paint = Layered::new(
+        Lambertian::new_form_color(Color::new(0.7, 0.05, 0.05)),
+        1.5,
+        0.0,
+    );
+    let rust = Lambertian::new_form_color(Color::new(0.35, 0.12, 0.04));
+    objects.add(gallery_sphere(0, 4., Lambertian::new_form_color(clay)));
+    objects.add(gallery_sphere(1, 4., OrenNayar::new_form_color(clay, 60.)));
+    objects.add(gallery_sphere(2, 4., paint));
+    objects.add(gallery_sphere(
+        3,
+        4.,
+        Mix::new(Conductor::new_copper(0.25), rust, NoiseTexture::new(3.)),
+    ));
+    objects.add(gallery_sphere(
+        4,
+        4.,
+        Mix::new(paint, Conductor::new_aluminium(0.4), NoiseTexture::new(8.)),
+    ));
+    objects.add(gallery_sphere(
+        5,
+        4.,
+        Mix::new_form_value(
+            Lambertian::new_form_color(Color::new(0.8, 0.8, 0.8)),
+            Conductor::new_gold(0.2),
+            0.5,
+        ),
+    ));
+
+    add_area_light(
+        &mut objects,
+        &mut lights,
+        ceiling_light(-4., 4., -4., 4., 10., 12.),
+    );
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 10.0, 18.0),
+        Point3::new(0.0, 0.5, 0.0),
+        aspect_ratio,
+    );
+
+    (objects, lights, cam)
+}
+
+// 参与介质: 后排为网格体积(云和火焰), 前排为不同相函数的球, 介质边界和充满场景的雾
+pub fn media_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
+    // World
+    let mut objects = HittableList::default();
+    let mut lights = HittableList::default();
+
+    add_checker_ground(
+        &mut objects,
+        Color::new(0.2, 0.2, 0.2),
+        Color::new(0.8, 0.8, 0.8),
+    );
+
+    let dummy = Lambertian::new_form_color(Color::new(0.0, 0.0, 0.0));
+
+    // 云, 球形的外轮廓上叠加Perlin湍流, 大部分体素为空, 用稀疏存储
+    let noise = NoiseTexture::new(3.0);
+    let cloud = DensityGrid::new_from_fn(64, 48, 64, |p| {
+        let d = p - Point3::new(0.5, 0.5, 0.5);
+        let falloff = 1.0 - (d.x * d.x + 1.8 * d.y * d.y + d.z * d.z).sqrt() * 2.2;
+        (falloff + 0.6 * noise.turb(p) - 0.3).max(0.0) * 2.0
+    })
+    .into_sparse();
+    let (cloud_min, cloud_max) = (Point3::new(-4.5, 1.0, -5.5), Point3::new(-0.5, 4.0, -2.5));
+    objects.add(Arc::new(MediumBoundary::new(
+        Cuboid::new(cloud_min, cloud_max, dummy),
+        GridMedium::new(
+            cloud,
+            cloud_min,
+            cloud_max,
+            6.0,
+            Color::new(0.98, 0.98, 0.98),
+            HenyeyGreenstein::new(0.6),
+        ),
+    )));
+
+    // 火焰, 越往上越细越冷, 黑烟主要吸收, 吸收的部分按温度发光
+    let flame = |p: Point3| {
+        let r = ((p.x - 0.5).powi(2) + (p.z - 0.5).powi(2)).sqrt();
+        let radius = 0.35 * (1.0 - 0.7 * p.y);
+        ((1.0 - r / radius).max(0.0) * (0.5 + noise.turb(2.0 * p))).min(1.0)
+    };
+    let smoke = DensityGrid::new_from_fn(48, 64, 48, |p| flame(p) * 4.0);
+    let temperature = DensityGrid::new_from_fn(48, 64, 48, |p| {
+        let t = flame(p) * (1.0 - p.y);
+        if t > 0.05 {
+            800.0 + 1400.0 * t
+        } else {
+            0.0
+        }
+    });
+    let (fire_min, fire_max) = (Point3::new(1.0, 0.01, -5.0), Point3::new(3.0, 4.0, -3.0));
+    objects.add(Arc::new(MediumBoundary::new(
+        Cuboid::new(fire_min, fire_max, dummy),
+        GridMedium::new(
+            smoke,
+            fire_min,
+            fire_max,
+            3.0,
+            Color::new(0.3, 0.3, 0.3),
+            IsotropicPhase,
+        )
+        .with_temperature(temperature, 1.5),
+    )));
+
+    // 从左到右: 向前散射, 双HG, Rayleigh, 向后散射; 逆光时向前散射的最亮
+    let boundary = |x: f64| Sphere::new(Point3::new(x, 0.8, 1.), 0.8, dummy);
+    let white = Color::new(0.9, 0.9, 0.9);
+    objects.add(Arc::new(ConstantMedium::new_from_color_with_phase(
+        boundary(-6.25),
+        2.,
+        HenyeyGreenstein::new(0.8),
+        white,
+    )));
+    objects.add(Arc::new(ConstantMedium::new_from_color_with_phase(
+        boundary(-3.75),
+        2.,
+        DoubleHenyeyGreenstein::new(0.8, -0.3, 0.8),
+        white,
+    )));
+    objects.add(Arc::new(ConstantMedium::new_from_color_with_phase(
+        boundary(-1.25),
+        2.,
+        Rayleigh,
+        white,
+    )));
+    objects.add(Arc::new(ConstantMedium::new_from_color_with_phase(
+        boundary(1.25),
+        2.,
+        HenyeyGreenstein::new(-0.5),
+        white,
+    )));
+
+    // 只吸收的有色液体装在玻璃球里, 向前散射的烟雾
+    objects.add(Arc::new(MediumBoundary::new_with_surface(
+        Sphere::new(Point3::new(3.75, 0.8, 1.), 0.8, Dielectric::new(1.33)),
+        Homogeneous::new_isotropic(Color::new(0.1, 0.9, 1.6), Color::new(0.0, 0.0, 0.0)),
+    )));
+    objects.add(Arc::new(MediumBoundary::new(
+        Cuboid::new(
+            Point3::new(5.55, 0.01, 0.3),
+            Point3::new(6.95, 1.6, 1.7),
+            dummy,
+        ),
+        Homogeneous::new(
+            Color::new(0.05, 0.05, 0.05),
+            Color::new(2.0, 2.0, 2.0),
+            HenyeyGreenstein::new(0.7),
+        ),
+    )));
+
+    // 整个场景充满稀薄的雾, 聚光灯照出光柱
+    objects.set_medium(Arc::new(Homogeneous::new(
+        Color::new(0.002, 0.002, 0.002),
+        Color::new(0.02, 0.02, 0.02),
+        HenyeyGreenstein::new(0.3),
+    )));
+    lights.add(Arc::new(SpotLight::new(
+        Point3::new(-3., 7., 4.),
+        Point3::new(0., 0., 0.),
+        Color::new(150., 140., 120.),
+        25.,
+        18.,
+    )));
+
+    // 充满雾的场景里平行光照不进来, 用物体后方朝向相机的聚光灯逆光照明, 另有一块面光源从前上方补光
+    lights.add(Arc::new(SpotLight::new(
+        Point3::new(0., 3., -9.),
+        Point3::new(0., 0.8, 1.),
+        Color::new(400., 380., 350.),
+        35.,
+        25.,
+    )));
+    add_area_light(
+        &mut objects,
+        &mut lights,
+        ceiling_light(-2., 2., 4., 6., 8., 6.),
+    );
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 4.0, 16.0),
+        Point3::new(0.0, 1.5, -1.0),
+        aspect_ratio,
+    );
+
+    (objects, lights, cam)
+}
+
+// 物体内部: 后排为嵌套的电介质(一杯加了冰块的水)和有吸收的玻璃, 前排为次表面散射
+pub fn interior_scene(aspect_ratio: f64) -> (HittableList, HittableList, Camera) {
+    // World
+    let mut objects = HittableList::default();
+    let mut lights = HittableList::default();
+
+    add_checker_ground(
+        &mut objects,
+        Color::new(0.2, 0.2, 0.2),
+        Color::new(0.8, 0.8, 0.8),
+    );
+
+    // 杯子是实心的玻璃块, 水和杯口的空气与它重叠, 重叠部分按优先级划分
+    // 水的顶面被空气覆盖, 实际的水面是空气的底面; 冰块一半露出水面
+    let glass = Dielectric::new(1.5).with_absorption(Color::new(0.85, 0.95, 0.9), 1.0);
+    let water = Dielectric::new(1.33)
+        .with_absorption(Color::new(0.6, 0.85, 0.95), 1.0)
+        .with_priority(1);
+    let air = Dielectric::new(1.0).with_priority(2);
+    let ice = Dielectric::new(1.31).with_priority(3);
+    objects.add(Arc::new(Cuboid::new(
+        Point3::new(-1.0, 0.01, -4.0),
+        Point3::new(1.0, 2.5, -2.0),
+        glass,
+    )));
+    objects.add(Arc::new(Cuboid::new(
+        Point3::new(-0.9, 0.15, -3.9),
+        Point3::new(0.9, 1.7, -2.1),
+        water,
+    )));
+    objects.add(Arc::new(Cuboid::new(
+        Point3::new(-0.9, 1.6, -3.9),
+        Point3::new(0.9, 2.6, -2.1),
+        air,
+    )));
+    objects.add(Arc::new(Translation::new(
+        RotationY::new(
+            Cuboid::new(
+                Point3::new(-0.3, -0.3, -0.3),
+                Point3::new(0.3, 0.3, 0.3),
+                ice,
+            ),
+            30.,
+        ),
+        Vec3::new(0.1, 1.6, -2.9),
+    )));
+
+    // 吸收相同的有色玻璃, 越厚颜色越深
+    let tinted = Dielectric::new(1.5).with_absorption(Color::new(0.9, 0.4, 0.2), 1.0);
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-3.2, 0.5, -2.5),
+        0.5,
+        tinted,
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(3.2, 1.0, -3.),
+        1.0,
+        tinted,
+    )));
+
+    // 次表面散射只用到边界的形状, 边界本身的材质被忽略
+    // 左: 大理石球, 中: 玉石人像, 右: 蜡块
+    let dummy = Lambertian::new_form_color(Color::new(0.0, 0.0, 0.0));
+    objects.add(Arc::new(
+        Subsurface::new_from_albedo(
+            Sphere::new(Point3::new(-2.8, 1., 1.), 1., dummy),
+            1.5,
+            Color::new(0.99, 0.99, 0.98),
+            Color::new(0.08, 0.08, 0.1),
+        )
+        .with_roughness(0.3),
+    ));
+    let patrick = ObjModel::new_from_file("models/patrick.obj", 1.2, dummy);
+    objects.add(Arc::new(
+        Subsurface::new_from_albedo(
+            Translation::new(patrick, Vec3::new(0., 0., 1.)),
+            1.6,
+            Color::new(0.6, 0.97, 0.75),
+            Color::new(0.15, 0.1, 0.15),
+        )
+        .with_roughness(0.2),
+    ));
+    objects.add(Arc::new(Subsurface::new(
+        Cuboid::new(Point3::new(2.1, 0., 0.3), Point3::new(3.5, 1.8, 1.7), dummy),
+        1.45,
+        Color::new(0.2, 0.8, 3.0),
+        Color::new(12., 12., 12.),
+    )));
+
+    lights.add(Arc::new(DirectionalLight::new(
+        Vec3::new(-0.5, -1., -0.8),
+        Color::new(2., 2., 2.),
+    )));
+    add_area_light(
+        &mut objects,
+        &mut lights,
+        ceiling_light(-3., 3., 0., 4., 8., 6.),
+    );
+
+    // Camera
+    let cam = demo_camera(
+        Point3::new(0.0, 4.5, 12.0),
+        Point3::new(0.0, 1.0, -1.0),
+        aspect_ratio,
+    );
+
+    (objects, lights, cam)
+}
@@ -3,6 +3,7 @@ pub mod image_texture;
 pub mod noise_texture;
 pub mod obj_texture;
 pub mod solid_color;
+pub mod uv_texture;
 
 use crate::utility::*;
 
@@ -20,6 +20,11 @@ impl NoiseTexture {
             scale,
         }
     }
+
+    // 湍流, 取值大致在 [0, 1.5], 用于程序化生成云和烟雾的密度
+    pub fn turb(&self, p: Point3) -> f64 {
+        self.noise.turb(self.scale * p, 7)
+    }
 }
 
 impl Texture for NoiseTexture {
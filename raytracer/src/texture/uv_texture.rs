@@ -0,0 +1,28 @@
+use super::Texture;
+use crate::utility::*;
+
+use std::sync::Arc;
+
+// 三角面击中点的 (u, v) 是重心坐标, 先用顶点的纹理坐标插值, 再查询内部的纹理
+// 内部纹理用Arc共享, 每个三角面只保存自己的三组纹理坐标
+#[derive(Clone)]
+pub struct TriangleUV<T: Texture> {
+    uv: [(f64, f64); 3],
+    tex: Arc<T>,
+}
+
+impl<T: Texture> TriangleUV<T> {
+    pub fn new(uv: [(f64, f64); 3], tex: Arc<T>) -> Self {
+        Self { uv, tex }
+    }
+}
+
+impl<T: Texture> Texture for TriangleUV<T> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uv;
+        let tu = u0 + u * (u1 - u0) + v * (u2 - u0);
+        let tv = v0 + u * (v1 - v0) + v * (v2 - v0);
+        // 超出 [0, 1] 的纹理坐标按重复处理
+        self.tex.value(tu - tu.floor(), tv - tv.floor(), p)
+    }
+}
@@ -0,0 +1,469 @@
+// 读取单层扫描线(scanline)格式的OpenEXR文件
+// 支持NONE, RLE, ZIPS, ZIP压缩和HALF, FLOAT, UINT通道, 其余压缩方式和分块(tiled)文件返回错误
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::convert::TryInto;
+use std::fs;
+
+const MAGIC: u32 = 20000630;
+
+const TILED_FLAG: u32 = 0x200;
+const DEEP_FLAG: u32 = 0x800;
+const MULTIPART_FLAG: u32 = 0x1000;
+
+const NO_COMPRESSION: u8 = 0;
+const RLE_COMPRESSION: u8 = 1;
+const ZIPS_COMPRESSION: u8 = 2;
+const ZIP_COMPRESSION: u8 = 3;
+
+const UINT: i32 = 0;
+const HALF: i32 = 1;
+const FLOAT: i32 = 2;
+
+struct Channel {
+    name: String,
+    pixel_type: i32,
+    x_sampling: i32,
+    y_sampling: i32,
+}
+
+impl Channel {
+    fn bytes(&self) -> usize {
+        if self.pixel_type == HALF {
+            2
+        } else {
+            4
+        }
+    }
+
+    fn value(&self, data: &[u8]) -> f32 {
+        match self.pixel_type {
+            UINT => u32::from_le_bytes(data[..4].try_into().unwrap()) as f32,
+            HALF => half_to_f32(u16::from_le_bytes(data[..2].try_into().unwrap())),
+            _ => f32::from_le_bytes(data[..4].try_into().unwrap()),
+        }
+    }
+}
+
+// 返回宽, 高和从上到下逐行排列的RGB像素, 只有Y通道的灰度图三个分量相同
+pub fn read_exr(filename: &str) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    let bytes = fs::read(filename).map_err(|e| e.to_string())?;
+    decode_exr(&bytes)
+}
+
+fn decode_exr(bytes: &[u8]) -> Result<(usize, usize, Vec<[f32; 3]>), String> {
+    let mut reader = Reader::new(bytes);
+
+    if reader.u32()? != MAGIC {
+        return Err("not an OpenEXR file".to_string());
+    }
+    let version = reader.u32()?;
+    if version & (TILED_FLAG | DEEP_FLAG | MULTIPART_FLAG) != 0 {
+        return Err("tiled, deep and multi-part images are not supported".to_string());
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _type_name = reader.string()?;
+        let size = reader.i32()? as usize;
+        let mut value = Reader::new(reader.bytes(size)?);
+        match name.as_str() {
+            "channels" => loop {
+                let name = value.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                value.bytes(4)?; // pLinear和保留字节
+                channels.push(Channel {
+                    name,
+                    pixel_type,
+                    x_sampling: value.i32()?,
+                    y_sampling: value.i32()?,
+                });
+            },
+            "compression" => compression = Some(value.bytes(1)?[0]),
+            "dataWindow" => {
+                data_window = Some((value.i32()?, value.i32()?, value.i32()?, value.i32()?))
+            }
+            _ => {}
+        }
+    }
+
+    let compression = compression.ok_or("missing compression attribute")?;
+    let (x_min, y_min, x_max, y_max) = data_window.ok_or("missing dataWindow attribute")?;
+    let width = (x_max - x_min + 1).max(0) as usize;
+    let height = (y_max - y_min + 1).max(0) as usize;
+    let lines_per_block = match compression {
+        NO_COMPRESSION | RLE_COMPRESSION | ZIPS_COMPRESSION => 1,
+        ZIP_COMPRESSION => 16,
+        _ => {
+            return Err(format!(
+                "compression method {} is not supported",
+                compression
+            ))
+        }
+    };
+    if channels
+        .iter()
+        .any(|c| c.x_sampling != 1 || c.y_sampling != 1)
+    {
+        return Err("subsampled channels are not supported".to_string());
+    }
+    if channels
+        .iter()
+        .any(|c| c.pixel_type < UINT || c.pixel_type > FLOAT)
+    {
+        return Err("unknown pixel type".to_string());
+    }
+
+    // 通道按名字排序存储, 每个通道在一行内的起始位置
+    let find = |name: &str| channels.iter().position(|c| c.name == name);
+    let rgb = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err("no RGB or Y channels".to_string()),
+    };
+    let mut channel_offset = Vec::with_capacity(channels.len());
+    let mut line_size = 0;
+    for channel in &channels {
+        channel_offset.push(line_size);
+        line_size += channel.bytes() * width;
+    }
+
+    let block_count = (0..height).step_by(lines_per_block).count();
+    let mut offsets = Vec::with_capacity(block_count);
+    for _ in 0..block_count {
+        offsets.push(reader.u64()? as usize);
+    }
+
+    let mut pixels = vec![[0.0_f32; 3]; width * height];
+    for offset in offsets {
+        let mut chunk = Reader::new(bytes.get(offset..).ok_or("chunk offset out of range")?);
+        let y = chunk.i32()?;
+        let size = chunk.i32()? as usize;
+        let data = chunk.bytes(size)?;
+
+        let first = (y - y_min) as usize;
+        let lines = lines_per_block.min(height.saturating_sub(first));
+        let expected = line_size * lines;
+        // 压缩后没有变小的块按原样存储
+        let data = if size >= expected || compression == NO_COMPRESSION {
+            data.to_vec()
+        } else if compression == RLE_COMPRESSION {
+            reconstruct(&rle_decode(data, expected)?)
+        } else {
+            reconstruct(&zlib_decode(data)?)
+        };
+        if data.len() < expected {
+            return Err("truncated chunk".to_string());
+        }
+
+        for line in 0..lines {
+            let row = &data[line * line_size..(line + 1) * line_size];
+            for x in 0..width {
+                let pixel = &mut pixels[(first + line) * width + x];
+                for (k, &c) in rgb.iter().enumerate() {
+                    let channel = &channels[c];
+                    pixel[k] = channel.value(&row[channel_offset[c] + x * channel.bytes()..]);
+                }
+            }
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h >> 15) as u32) << 31;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        // 非规格化数
+        0 => return f32::from_bits(sign) + (mantissa as f32) * 2.0_f32.powi(-24),
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+// 压缩前的预处理的逆过程: 先对字节差分求和, 再把前后两半交错回原来的顺序
+fn reconstruct(data: &[u8]) -> Vec<u8> {
+    let mut t = data.to_vec();
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len() - t.len() / 2;
+    let mut out = Vec::with_capacity(t.len());
+    for i in 0..half {
+        out.push(t[i]);
+        if half + i < t.len() {
+            out.push(t[half + i]);
+        }
+    }
+    out
+}
+
+fn rle_decode(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+        if count < 0 {
+            let end = i + (-(count as i32)) as usize;
+            out.extend_from_slice(data.get(i..end).ok_or("truncated RLE data")?);
+            i = end;
+        } else {
+            let value = *data.get(i).ok_or("truncated RLE data")?;
+            out.resize(out.len() + count as usize + 1, value);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let data = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or("unexpected end of file")?;
+        self.pos += n;
+        Ok(data)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unterminated string")?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+// ZIP和ZIPS压缩的块是zlib格式的deflate数据
+fn zlib_decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    decompress_to_vec_zlib(data).map_err(|e| format!("invalid zlib data: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 20; // ZIP每块16行, 最后一块不满
+
+    // 每4个像素一样, 使每行都能被压缩, 各值都能用half精确表示
+    fn test_pixels() -> Vec<[f32; 3]> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let (x, y) = ((i % WIDTH / 4) as f32, (i / WIDTH) as f32);
+                [x * 0.25, y * 0.5, 1.0 + (x + y) * 0.125]
+            })
+            .collect()
+    }
+
+    fn f32_to_half(v: f32) -> u16 {
+        if v == 0.0 {
+            return 0;
+        }
+        let bits = v.to_bits();
+        let exponent = ((bits >> 23) & 0xff) - 112;
+        ((bits >> 16) & 0x8000) as u16 | (exponent << 10) as u16 | ((bits >> 13) & 0x3ff) as u16
+    }
+
+    // reconstruct的逆过程
+    fn predict(data: &[u8]) -> Vec<u8> {
+        let mut t: Vec<u8> = data.iter().step_by(2).copied().collect();
+        t.extend(data.iter().skip(1).step_by(2));
+        let mut out = t.clone();
+        for i in 1..t.len() {
+            out[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+        }
+        out
+    }
+
+    fn rle_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let run = data[i..]
+                .iter()
+                .take(128)
+                .take_while(|&&b| b == data[i])
+                .count();
+            if run >= 3 {
+                out.push((run - 1) as u8);
+                out.push(data[i]);
+                i += run;
+            } else {
+                let mut end = i + 1;
+                while end < data.len()
+                    && end - i < 127
+                    && !(end + 2 < data.len()
+                        && data[end] == data[end + 1]
+                        && data[end] == data[end + 2])
+                {
+                    end += 1;
+                }
+                out.push((-((end - i) as i32)) as u8);
+                out.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+        }
+        out
+    }
+
+    fn attribute(out: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+        for s in [name, type_name].iter() {
+            out.extend_from_slice(s.as_bytes());
+            out.push(0);
+        }
+        out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+
+    // 按给定的压缩方式和通道类型生成一个RGB扫描线文件
+    fn encode_exr(compression: u8, pixel_type: i32, pixels: &[[f32; 3]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC.to_le_bytes());
+        out.extend_from_slice(&2_u32.to_le_bytes());
+
+        let mut channels = Vec::new();
+        for name in ["B", "G", "R"].iter() {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&pixel_type.to_le_bytes());
+            channels.extend_from_slice(&[0; 4]);
+            channels.extend_from_slice(&1_i32.to_le_bytes());
+            channels.extend_from_slice(&1_i32.to_le_bytes());
+        }
+        channels.push(0);
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[compression]);
+        let mut window = Vec::new();
+        for v in [0, 0, WIDTH as i32 - 1, HEIGHT as i32 - 1].iter() {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        attribute(&mut out, "dataWindow", "box2i", &window);
+        attribute(&mut out, "displayWindow", "box2i", &window);
+        out.push(0);
+
+        let lines_per_block = if compression == ZIP_COMPRESSION {
+            16
+        } else {
+            1
+        };
+        let mut chunks = Vec::new();
+        for first in (0..HEIGHT).step_by(lines_per_block) {
+            let mut raw = Vec::new();
+            for y in first..(first + lines_per_block).min(HEIGHT) {
+                for k in [2, 1, 0].iter() {
+                    for x in 0..WIDTH {
+                        let v = pixels[y * WIDTH + x][*k];
+                        match pixel_type {
+                            HALF => raw.extend_from_slice(&f32_to_half(v).to_le_bytes()),
+                            UINT => raw.extend_from_slice(&(v as u32).to_le_bytes()),
+                            _ => raw.extend_from_slice(&v.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+            let data = match compression {
+                RLE_COMPRESSION => rle_encode(&predict(&raw)),
+                ZIPS_COMPRESSION | ZIP_COMPRESSION => compress_to_vec_zlib(&predict(&raw), 6),
+                _ => raw.clone(),
+            };
+            if compression != NO_COMPRESSION {
+                assert!(data.len() < raw.len(), "block didn't compress");
+            }
+            chunks.push((first, data));
+        }
+
+        let mut offset = out.len() + 8 * chunks.len();
+        for (_, data) in chunks.iter() {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += 8 + data.len();
+        }
+        for (first, data) in chunks {
+            out.extend_from_slice(&(first as i32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+
+    fn check_round_trip(compression: u8, pixel_type: i32) {
+        let pixels = test_pixels();
+        let bytes = encode_exr(compression, pixel_type, &pixels);
+        let (width, height, decoded) = decode_exr(&bytes).unwrap();
+        assert_eq!((width, height), (WIDTH, HEIGHT));
+        assert_eq!(decoded, pixels);
+    }
+
+    #[test]
+    fn reads_uncompressed() {
+        check_round_trip(NO_COMPRESSION, HALF);
+        check_round_trip(NO_COMPRESSION, FLOAT);
+    }
+
+    #[test]
+    fn reads_rle() {
+        check_round_trip(RLE_COMPRESSION, HALF);
+        check_round_trip(RLE_COMPRESSION, FLOAT);
+    }
+
+    #[test]
+    fn reads_zips() {
+        check_round_trip(ZIPS_COMPRESSION, HALF);
+        check_round_trip(ZIPS_COMPRESSION, FLOAT);
+    }
+
+    #[test]
+    fn reads_zip() {
+        check_round_trip(ZIP_COMPRESSION, HALF);
+        check_round_trip(ZIP_COMPRESSION, FLOAT);
+    }
+
+    #[test]
+    fn rejects_unsupported_compression() {
+        let mut bytes = encode_exr(NO_COMPRESSION, HALF, &test_pixels());
+        let pos = bytes
+            .windows(12)
+            .position(|w| w == b"compression\0")
+            .unwrap();
+        // 名字, 类型名和4字节长度之后就是压缩方式
+        bytes[pos + 12 + 12 + 4] = 4; // PIZ
+        assert!(decode_exr(&bytes).is_err());
+    }
+}
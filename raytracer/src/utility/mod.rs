@@ -4,4 +4,5 @@ pub use std::f64::consts::PI;
 pub use std::f64::{INFINITY, NEG_INFINITY};
 
 pub mod export;
+pub mod exr;
 pub mod multi_thread;
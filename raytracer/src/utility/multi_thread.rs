@@ -1,29 +1,35 @@
 use image::pnm::SampleEncoding;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 
+use crate::basic::background::Background;
 use crate::basic::camera::Camera;
 use crate::basic::ray;
 use crate::hittable::hittable_list::HittableList;
 use crate::scene;
 use crate::utility::*;
 use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 
 use console::style;
 
 use rand::Rng;
 
+pub const THREAD_NUM: usize = 8;
+
 pub fn gen_img_with_multi_threads(
     thread_num: usize,
     img_width: usize,
     img_height: usize,
     sample_per_pixel: usize,
     max_depth: usize,
-    background: Color,
+    background: Arc<dyn Background>,
     world: HittableList,
-    lights: HittableList,
+    mut lights: HittableList,
     camera: Camera,
 ) -> Vec<(JoinHandle<()>, Receiver<Vec<Color>>)> {
+    lights.build_light_sampler();
+
     println!("🕐 Generating image...");
     println!(
         "   Image size: {}",
@@ -50,6 +56,7 @@ pub fn gen_img_with_multi_threads(
         let (tx, rx) = mpsc::channel();
         let section_world = world.clone();
         let section_lights = lights.clone();
+        let section_background = background.clone();
         let cam = camera.clone();
         let progress = multiprogress.add(create_progress_bar(
             (img_width * (row_end - row_beg)) as u64,
@@ -69,7 +76,7 @@ pub fn gen_img_with_multi_threads(
                             let r = cam.get_ray(u, v);
                             pixel_color += ray::ray_color(
                                 &r,
-                                &background,
+                                section_background.as_ref(),
                                 &section_world,
                                 &section_lights,
                                 max_depth,